@@ -7,3 +7,23 @@ pub trait Comparator {
 
     fn find_short_successor(&self, key: &[u8]) -> Vec<u8>;
 }
+
+// lets a shared comparator be wrapped, e.g. the user comparator of a `Config` in an
+// `InternalKeyComparator`
+impl<C: Comparator + ?Sized> Comparator for std::sync::Arc<C> {
+    fn compare(&self, a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+        (**self).compare(a, b)
+    }
+
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn find_shortest_separator(&self, a: &[u8], b: &[u8]) -> Vec<u8> {
+        (**self).find_shortest_separator(a, b)
+    }
+
+    fn find_short_successor(&self, key: &[u8]) -> Vec<u8> {
+        (**self).find_short_successor(key)
+    }
+}
@@ -1,8 +1,19 @@
-use std::sync::Arc;
-
-use crate::{comparator::Comparator, filter::FilterPolicy, utils::comparators::BytewiseComparator};
-
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    comparator::Comparator,
+    filter::{FilterPolicy, InternalFilterPolicy},
+    utils::{
+        clock::{Clock, SystemClock},
+        comparators::{BytewiseComparator, InternalKeyComparator, KeyTailEncoding},
+    },
+};
+
+#[derive(Clone)]
 pub struct Config {
+    /// Entries between restart points of a block, keys within a run share
+    /// prefixes. With 1 every key is stored in full and seek binary searches
+    /// down to the exact entry, at the cost of space and a restart point each.
     pub(crate) block_restart_interval: u32,
 
     pub(crate) comparator: Arc<dyn Comparator>,
@@ -12,6 +23,51 @@ pub struct Config {
     pub(crate) block_size: usize,
 
     pub(crate) compresstion_type: CompressionType,
+
+    /// Bytes of disk reserved for a table file when its builder is created, 0 disables it.
+    pub(crate) preallocate_size: u64,
+
+    pub(crate) duplicate_keys: DuplicateKeyPolicy,
+
+    pub(crate) sync_policy: SyncPolicy,
+
+    pub(crate) clock: Arc<dyn Clock>,
+
+    /// Flush and compaction jobs run at the same time, at least 1.
+    pub(crate) max_background_jobs: usize,
+
+    /// Check the crc of every block when a table is opened, so a corrupted
+    /// table fails to open rather than on the first read of the bad block.
+    pub(crate) paranoid_checks: bool,
+}
+
+impl Config {
+    /// Layer per table settings over this config.
+    pub fn with_table_options(&self, opts: &TableOptions) -> Arc<Config> {
+        Arc::new(Config {
+            block_restart_interval: opts
+                .block_restart_interval
+                .unwrap_or(self.block_restart_interval),
+            block_size: opts.block_size.unwrap_or(self.block_size),
+            compresstion_type: opts.compression.unwrap_or(self.compresstion_type),
+            preallocate_size: opts.preallocate_size.unwrap_or(self.preallocate_size),
+            ..self.clone()
+        })
+    }
+
+    /// The config of tables whose keys are internal keys of this config's user keys.
+    /// Its filter policy filters the user keys, so a lookup at any seq may match.
+    pub fn with_internal_keys(&self) -> Arc<Config> {
+        let filter_policy = self.filter_policy.clone().map(|policy| {
+            Arc::new(InternalFilterPolicy::new(policy, KeyTailEncoding::Fixed))
+                as Arc<dyn FilterPolicy>
+        });
+        Arc::new(Config {
+            comparator: Arc::new(InternalKeyComparator::new(self.comparator.clone())),
+            filter_policy,
+            ..self.clone()
+        })
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -21,12 +77,42 @@ pub enum CompressionType {
     Snappy = 1, // not impl
 }
 
+/// What a table builder does with a key equal to the previous one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateKeyPolicy {
+    /// Store every entry.
+    Keep,
+    /// Keep the first entry and drop the later ones.
+    Skip,
+    /// Fail with `InvalidArgument`, keys must be strictly increasing.
+    Reject,
+}
+
+/// When the redo log is fsynced, trading durability for write throughput.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPolicy {
+    /// Leave it to the OS, a crash may lose any unsynced write.
+    Never,
+    /// Sync after every write.
+    EveryWrite,
+    /// Sync after every n writes.
+    EveryN(usize),
+    /// Sync on the first write after the interval has passed since the last sync.
+    Interval(Duration),
+}
+
 pub struct ConfigBuilder {
     block_restart_interval: u32,
     comparator: Arc<dyn Comparator>,
     filter_policy: Option<Arc<dyn FilterPolicy>>,
     block_size: usize,
     compression: CompressionType,
+    preallocate_size: u64,
+    duplicate_keys: DuplicateKeyPolicy,
+    sync_policy: SyncPolicy,
+    clock: Arc<dyn Clock>,
+    max_background_jobs: usize,
+    paranoid_checks: bool,
 }
 
 impl Default for ConfigBuilder {
@@ -36,6 +122,12 @@ impl Default for ConfigBuilder {
         let filter_policy = None;
         let block_size = 4096;
         let compression = CompressionType::None;
+        let preallocate_size = 0;
+        let duplicate_keys = DuplicateKeyPolicy::Keep;
+        let sync_policy = SyncPolicy::Never;
+        let clock = Arc::new(SystemClock);
+        let max_background_jobs = 2;
+        let paranoid_checks = false;
 
         Self {
             block_restart_interval,
@@ -43,6 +135,12 @@ impl Default for ConfigBuilder {
             filter_policy,
             block_size,
             compression,
+            preallocate_size,
+            duplicate_keys,
+            sync_policy,
+            clock,
+            max_background_jobs,
+            paranoid_checks,
         }
     }
 }
@@ -77,6 +175,36 @@ impl ConfigBuilder {
         self
     }
 
+    pub fn preallocate_size(&mut self, size: u64) -> &mut Self {
+        self.preallocate_size = size;
+        self
+    }
+
+    pub fn duplicate_keys(&mut self, policy: DuplicateKeyPolicy) -> &mut Self {
+        self.duplicate_keys = policy;
+        self
+    }
+
+    pub fn sync_policy(&mut self, policy: SyncPolicy) -> &mut Self {
+        self.sync_policy = policy;
+        self
+    }
+
+    pub fn clock(&mut self, clock: Arc<dyn Clock>) -> &mut Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn max_background_jobs(&mut self, jobs: usize) -> &mut Self {
+        self.max_background_jobs = jobs;
+        self
+    }
+
+    pub fn paranoid_checks(&mut self, enable: bool) -> &mut Self {
+        self.paranoid_checks = enable;
+        self
+    }
+
     pub fn build(&self) -> Arc<Config> {
         Arc::new(Config {
             block_restart_interval: self.block_restart_interval,
@@ -84,6 +212,42 @@ impl ConfigBuilder {
             filter_policy: self.filter_policy.clone(),
             block_size: self.block_size,
             compresstion_type: self.compression,
+            preallocate_size: self.preallocate_size,
+            duplicate_keys: self.duplicate_keys,
+            sync_policy: self.sync_policy,
+            clock: self.clock.clone(),
+            max_background_jobs: self.max_background_jobs,
+            paranoid_checks: self.paranoid_checks,
         })
     }
 }
+
+/// Settings of a single table that override the [`Config`] it is built with,
+/// e.g. small blocks for L0 and bigger ones for deeper levels.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TableOptions {
+    pub block_size: Option<usize>,
+    pub block_restart_interval: Option<u32>,
+    pub compression: Option<CompressionType>,
+    pub preallocate_size: Option<u64>,
+}
+
+/// Options that control a single read.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadOptions {
+    /// Verify the crc32 of every record read from disk.
+    ///
+    /// Turning this off skips hashing on the read path, which is only safe for
+    /// data that was just written by this process and is known to be intact.
+    /// Torn writes or bit rot are then returned to the caller as-is instead of
+    /// being reported as corruption.
+    pub verify_checksums: bool,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        Self {
+            verify_checksums: true,
+        }
+    }
+}
@@ -1,16 +1,106 @@
+use std::path::PathBuf;
+
 #[derive(Debug, thiserror::Error)]
 pub enum DBError {
     #[error("IO: {0}")]
     IO(#[from] std::io::Error),
 
-    // #[error("VarInt: {0}")]
-    // VarInt(#[from] VarIntError),
+    #[error("VarInt: {0}")]
+    VarInt(#[from] VarIntError),
+
     #[error("Corruption: {0}")]
-    Corruption(Box<dyn std::error::Error>),
+    Corruption(#[from] Corruption),
+
+    #[error("Block too large: {0} bytes of entries")]
+    BlockTooLarge(usize),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+}
+
+impl DBError {
+    /// Name the file of a corruption that does not name one yet, other errors
+    /// are returned as they are.
+    pub fn in_file(self, file: impl Into<PathBuf>) -> Self {
+        match self {
+            DBError::Corruption(c) if c.file.is_none() => c.in_file(file).into(),
+            e => e,
+        }
+    }
 }
 
 pub type DBResult<T, E = DBError> = std::result::Result<T, E>;
 
+/// The single error type of the public API, lower level errors convert into it with `?`.
+pub type Error = DBError;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// What is wrong with the corrupted data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CorruptionKind {
+    /// The stored crc does not match the data.
+    ChecksumMismatch,
+    /// The data ends before a complete record, block or footer.
+    Truncated,
+    /// The table footer does not end with the table magic number.
+    BadMagic,
+    /// A length field runs past the data it describes.
+    BadLength,
+    /// An unknown record type or compression type.
+    BadType,
+    /// A block handle or offset that does not decode or points nowhere.
+    BadOffset,
+    /// A block entry or restart point that does not decode.
+    BadEntry,
+}
+
+/// Where and how stored data was found corrupted. `file` and `offset` are set
+/// when the reader knows them, `offset` counts bytes from the start of the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Corruption {
+    pub kind: CorruptionKind,
+    pub file: Option<PathBuf>,
+    pub offset: Option<u64>,
+    pub detail: String,
+}
+
+impl Corruption {
+    pub fn new(kind: CorruptionKind, detail: impl Into<String>) -> Self {
+        Self {
+            kind,
+            file: None,
+            offset: None,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn at(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub fn in_file(mut self, file: impl Into<PathBuf>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+}
+
+impl std::fmt::Display for Corruption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.detail)?;
+        if let Some(offset) = self.offset {
+            write!(f, " at offset {}", offset)?;
+        }
+        if let Some(file) = &self.file {
+            write!(f, " in {}", file.display())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Corruption {}
+
 #[derive(Debug, thiserror::Error)]
 pub enum VarIntError {
     #[error("Insufficient bytes")]
@@ -19,3 +109,48 @@ pub enum VarIntError {
     #[error("Overflow")]
     Overflow,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::varint::VarInt;
+
+    use super::{Corruption, CorruptionKind, Error, Result, VarIntError};
+
+    fn decode(buf: &[u8]) -> Result<u64> {
+        let (n, _) = u64::from_varint(buf)?;
+        Ok(n)
+    }
+
+    fn open(path: &str) -> Result<std::fs::File> {
+        Ok(std::fs::File::open(path)?)
+    }
+
+    #[test]
+    fn convert_with_question_mark() {
+        assert_eq!(decode(&[0x96, 0x01]).unwrap(), 150);
+        assert!(matches!(
+            decode(&[0x96]),
+            Err(Error::VarInt(VarIntError::InsufficientBytes))
+        ));
+        assert!(matches!(
+            open("/nonexistent/arisdb"),
+            Err(Error::IO(e)) if e.kind() == std::io::ErrorKind::NotFound
+        ));
+    }
+
+    #[test]
+    fn corruption_display() {
+        let c = Corruption::new(CorruptionKind::ChecksumMismatch, "block checksum mismatch");
+        assert_eq!(c.to_string(), "block checksum mismatch");
+
+        let c = c.at(4096).in_file("000012.sst");
+        assert_eq!(
+            c.to_string(),
+            "block checksum mismatch at offset 4096 in 000012.sst"
+        );
+        assert_eq!(
+            Error::from(c).to_string(),
+            "Corruption: block checksum mismatch at offset 4096 in 000012.sst"
+        );
+    }
+}
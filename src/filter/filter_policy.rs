@@ -1,4 +1,10 @@
-use crate::utils::bloom::BloomBuilder;
+use std::sync::Arc;
+
+use crate::utils::{
+    bloom::BloomBuilder,
+    comparators::KeyTailEncoding,
+    hash::{BasicHasher, Hasher32},
+};
 
 pub trait FilterPolicy {
     fn name(&self) -> &str;
@@ -9,12 +15,32 @@ pub trait FilterPolicy {
 }
 
 pub struct BloomFilter {
+    name: String,
     builder: BloomBuilder,
 }
 
+impl BloomFilter {
+    pub fn new(bits_per_key: usize) -> Self {
+        Self::with_hasher(bits_per_key, Arc::new(BasicHasher))
+    }
+
+    /// A bloom filter probing with `hasher`. Its name carries the hasher name, so a
+    /// table is never read with a filter built by another hash.
+    pub fn with_hasher(bits_per_key: usize, hasher: Arc<dyn Hasher32>) -> Self {
+        let name = match hasher.name() {
+            "basic" => "bloom".to_string(),
+            other => format!("bloom.{}", other),
+        };
+        Self {
+            name,
+            builder: BloomBuilder::with_hasher(bits_per_key, hasher),
+        }
+    }
+}
+
 impl FilterPolicy for BloomFilter {
     fn name(&self) -> &str {
-        "bloom"
+        &self.name
     }
 
     fn create_filter(&self, keys: &[Vec<u8>]) -> Vec<u8> {
@@ -22,6 +48,49 @@ impl FilterPolicy for BloomFilter {
     }
 
     fn may_contain(&self, filter: &[u8], key: &[u8]) -> bool {
-        BloomBuilder::may_contain(filter, &key)
+        self.builder.may_contain(filter, &key)
+    }
+}
+
+/// Filters the user keys of internal keys with the wrapped policy, the seq and
+/// value type tail is stripped before a key is added or looked up. A lookup at
+/// any seq then finds a user key added at another.
+pub struct InternalFilterPolicy {
+    user_policy: Arc<dyn FilterPolicy>,
+    tail_encoding: KeyTailEncoding,
+}
+
+impl InternalFilterPolicy {
+    pub fn new(user_policy: Arc<dyn FilterPolicy>, tail_encoding: KeyTailEncoding) -> Self {
+        Self {
+            user_policy,
+            tail_encoding,
+        }
+    }
+}
+
+impl FilterPolicy for InternalFilterPolicy {
+    // the filter holds user keys, the same as one built by the user policy
+    fn name(&self) -> &str {
+        self.user_policy.name()
+    }
+
+    fn create_filter(&self, keys: &[Vec<u8>]) -> Vec<u8> {
+        let user_keys: Vec<_> = keys
+            .iter()
+            .map(|key| match self.tail_encoding.split(key) {
+                Ok((user_key, _)) => user_key.to_vec(),
+                Err(_) => key.clone(),
+            })
+            .collect();
+        self.user_policy.create_filter(&user_keys)
+    }
+
+    // a key without a tail can't be looked up by its user key, it may be anywhere
+    fn may_contain(&self, filter: &[u8], key: &[u8]) -> bool {
+        match self.tail_encoding.split(key) {
+            Ok((user_key, _)) => self.user_policy.may_contain(filter, user_key),
+            Err(_) => true,
+        }
     }
 }
@@ -1,4 +1,3 @@
 mod filter_policy;
 
-pub use filter_policy::FilterPolicy;
-
+pub use filter_policy::{BloomFilter, FilterPolicy, InternalFilterPolicy};
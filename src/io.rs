@@ -0,0 +1,126 @@
+use std::{fs::File, sync::Arc};
+
+use crate::utils::fs::fadvise;
+
+/// How a file is about to be read, a hint for the kernel's readahead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessPattern {
+    Sequential,
+    Random,
+}
+
+/// The file operations the log and table code is built on. Every call blocks
+/// until the operation completes, like waiting on a `rio` completion does.
+pub trait IoBackend: Send + Sync {
+    fn read_at(&self, fd: &File, buf: &mut [u8], offset: u64) -> std::io::Result<usize>;
+
+    fn write_at(&self, fd: &File, buf: &[u8], offset: u64) -> std::io::Result<usize>;
+
+    fn fsync(&self, fd: &File) -> std::io::Result<()>;
+
+    fn advise(&self, fd: &File, pattern: AccessPattern) -> std::io::Result<()> {
+        fadvise(fd, pattern)
+    }
+
+    /// Read each buffer at its offset, returning the results in the same order.
+    /// Backends that can keep several reads in flight submit them all at once.
+    fn read_many(&self, fd: &File, reads: &mut [(&mut [u8], u64)]) -> Vec<std::io::Result<usize>> {
+        reads
+            .iter_mut()
+            .map(|(buf, offset)| self.read_at(fd, buf, *offset))
+            .collect()
+    }
+}
+
+/// Io through io_uring.
+pub struct RioBackend(rio::Rio);
+
+impl RioBackend {
+    pub fn new() -> std::io::Result<Self> {
+        Ok(Self(rio::new()?))
+    }
+}
+
+impl IoBackend for RioBackend {
+    fn read_at(&self, fd: &File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        self.0.read_at(fd, &buf, offset).wait()
+    }
+
+    fn write_at(&self, fd: &File, buf: &[u8], offset: u64) -> std::io::Result<usize> {
+        self.0.write_at(fd, &buf, offset).wait()
+    }
+
+    fn fsync(&self, fd: &File) -> std::io::Result<()> {
+        self.0.fsync(fd).wait()?;
+        Ok(())
+    }
+
+    fn read_many(&self, fd: &File, reads: &mut [(&mut [u8], u64)]) -> Vec<std::io::Result<usize>> {
+        let comps: Vec<_> = reads
+            .iter()
+            .map(|(buf, offset)| self.0.read_at(fd, buf, *offset))
+            .collect();
+        comps.into_iter().map(|comp| comp.wait()).collect()
+    }
+}
+
+/// Io through blocking positional reads and writes, for kernels and platforms
+/// without io_uring.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StdBackend;
+
+impl IoBackend for StdBackend {
+    fn read_at(&self, fd: &File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+        #[cfg(unix)]
+        return std::os::unix::fs::FileExt::read_at(fd, buf, offset);
+        #[cfg(windows)]
+        return std::os::windows::fs::FileExt::seek_read(fd, buf, offset);
+    }
+
+    fn write_at(&self, fd: &File, buf: &[u8], offset: u64) -> std::io::Result<usize> {
+        #[cfg(unix)]
+        return std::os::unix::fs::FileExt::write_at(fd, buf, offset);
+        #[cfg(windows)]
+        return std::os::windows::fs::FileExt::seek_write(fd, buf, offset);
+    }
+
+    fn fsync(&self, fd: &File) -> std::io::Result<()> {
+        fd.sync_all()
+    }
+}
+
+/// io_uring where it can be set up, otherwise the blocking std backend.
+pub fn default_backend() -> Arc<dyn IoBackend> {
+    match RioBackend::new() {
+        Ok(backend) => Arc::new(backend),
+        Err(e) => {
+            tracing::warn!("io_uring unavailable, falling back to std io: {}", e);
+            Arc::new(StdBackend)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::testing::TempFile;
+
+    use super::{IoBackend, StdBackend};
+
+    #[test]
+    fn std_read_many() {
+        let fd = TempFile::new("std-io");
+
+        assert_eq!(StdBackend.write_at(&fd, b"hello world", 0).unwrap(), 11);
+        StdBackend.fsync(&fd).unwrap();
+
+        let mut a = [0; 5];
+        let mut b = [0; 8];
+        let res = StdBackend.read_many(&fd, &mut [(&mut a[..], 0), (&mut b[..], 6)]);
+        assert_eq!(
+            res.into_iter().map(|r| r.unwrap()).collect::<Vec<_>>(),
+            [5, 5]
+        );
+        assert_eq!(&a, b"hello");
+        assert_eq!(&b[..5], b"world");
+    }
+}
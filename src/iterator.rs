@@ -18,4 +18,10 @@ pub trait Iterator {
     fn seek(&mut self, target: &[u8]);
 
     fn status(&mut self) -> DBResult<()>;
+
+    /// Rewind the iterator so it can be reused, implementations that can
+    /// drop their position cheaply should leave it unpositioned instead.
+    fn reset(&mut self) {
+        self.seek_to_first();
+    }
 }
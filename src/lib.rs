@@ -5,6 +5,7 @@ pub(crate) mod table;
 
 pub mod comparator;
 pub mod error;
+pub mod io;
 pub mod iterator;
 pub mod utils;
 pub mod filter;
@@ -51,93 +51,177 @@ impl Drop for DefaultAllocatorInner {
     }
 }
 
-// const BLOCK_SIZE: usize = 4096;
-
-// // Single-threaded safety
-// pub struct BlockArena {
-//     blocks: Vec<Vec<u8>>,
-//     alloc_ptr: *mut u8,
-//     alloc_bytes_remaining: usize,
-//     mem_alloc: AtomicUsize,
-// }
-
-// impl Default for BlockArena {
-//     fn default() -> Self {
-//         Self {
-//             blocks: Vec::new(),
-//             alloc_ptr: std::ptr::null_mut(),
-//             alloc_bytes_remaining: 0,
-//             mem_alloc: AtomicUsize::new(0),
-//         }
-//     }
-// }
-
-// impl BlockArena {
-//     pub fn new() -> Self {
-//         Self::default()
-//     }
-
-//     pub fn allocate(&mut self, bytes: usize) -> *mut u8 {
-//         if self.alloc_bytes_remaining >= bytes {
-//             let result = self.alloc_ptr;
-//             self.alloc_ptr = unsafe { self.alloc_ptr.add(bytes) };
-//             self.alloc_bytes_remaining -= bytes;
-//             return result;
-//         }
-
-//         self.allocate_fallback(bytes)
-//     }
-
-//     pub fn allocate_aligned(&mut self, bytes: usize, align: usize) -> *mut u8 {
-//         assert!(align % 2 == 0);
-
-//         let cmod = (self.alloc_ptr as usize) & (align - 1);
-//         let slop = if cmod == 0 { 0 } else { align - cmod };
-//         let needed = bytes + slop;
-
-//         let result = if needed <= self.alloc_bytes_remaining {
-//             unsafe {
-//                 let result = self.alloc_ptr.add(slop);
-//                 self.alloc_ptr = self.alloc_ptr.add(needed);
-//                 self.alloc_bytes_remaining -= needed;
-//                 result
-//             }
-//         } else {
-//             self.allocate_fallback(bytes)
-//         };
-
-//         assert!(
-//             result as usize & (align - 1) == 0,
-//             "result: {:?}, slop: {:?}, bytes: {:?}",
-//             result,
-//             slop,
-//             bytes,
-//         );
-//         result
-//     }
-
-//     fn allocate_fallback(&mut self, bytes: usize) -> *mut u8 {
-//         if bytes > BLOCK_SIZE / 4 {
-//             return self.allocate_new_block(bytes);
-//         }
-
-//         self.alloc_ptr = self.allocate_new_block(BLOCK_SIZE);
-//         self.alloc_bytes_remaining = BLOCK_SIZE;
-
-//         unsafe {
-//             let result = self.alloc_ptr;
-//             self.alloc_ptr = self.alloc_ptr.add(bytes);
-//             self.alloc_bytes_remaining -= bytes;
-//             result
-//         }
-//     }
-
-//     fn allocate_new_block(&mut self, bytes: usize) -> *mut u8 {
-//         let mem = vec![0_u8; bytes]; // aligned!
-//         let ptr = mem.as_ptr() as *mut u8;
-//         self.blocks.push(mem);
-//         self.mem_alloc
-//             .fetch_add(bytes, std::sync::atomic::Ordering::SeqCst);
-//         ptr
-//     }
-// }
+const BLOCK_SIZE: usize = 4096;
+const BLOCK_ALIGN: usize = 16;
+
+/// Carves allocations out of blocks of `block_size` bytes. Allocations larger than a
+/// quarter block get a block of their own, so memtables holding large values want a
+/// larger block size. Memory is released when the last clone is dropped.
+#[derive(Debug, Clone)]
+pub struct BlockArena(Arc<BlockArenaInner>);
+
+impl Default for BlockArena {
+    fn default() -> Self {
+        Self::with_block_size(BLOCK_SIZE)
+    }
+}
+
+impl BlockArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_block_size(block_size: usize) -> Self {
+        assert!(block_size > 0, "required block_size > 0");
+        Self(Arc::new(BlockArenaInner {
+            block_size,
+            state: Mutex::new(ArenaState {
+                blocks: Vec::new(),
+                alloc_ptr: std::ptr::null_mut(),
+                alloc_bytes_remaining: 0,
+            }),
+            mem_alloc: AtomicUsize::new(0),
+        }))
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.0.block_size
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.0.state.lock().unwrap().blocks.len()
+    }
+}
+
+impl MemAllocator for BlockArena {
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+        self.0.allocate(layout)
+    }
+
+    fn mem_usage(&self) -> usize {
+        self.0.mem_usage()
+    }
+}
+
+#[derive(Debug)]
+struct ArenaState {
+    blocks: Vec<(*mut u8, Layout)>,
+    alloc_ptr: *mut u8,
+    alloc_bytes_remaining: usize,
+}
+
+#[derive(Debug)]
+pub struct BlockArenaInner {
+    block_size: usize,
+    state: Mutex<ArenaState>,
+    mem_alloc: AtomicUsize,
+}
+
+impl BlockArenaInner {
+    fn allocate_fallback(&self, state: &mut ArenaState, layout: Layout) -> *mut u8 {
+        if layout.size() > self.block_size / 4 || layout.align() > BLOCK_ALIGN {
+            return self.allocate_new_block(state, layout.size(), layout.align());
+        }
+
+        let block = self.allocate_new_block(state, self.block_size, BLOCK_ALIGN);
+        unsafe {
+            state.alloc_ptr = block.add(layout.size());
+        }
+        state.alloc_bytes_remaining = self.block_size - layout.size();
+        block
+    }
+
+    fn allocate_new_block(&self, state: &mut ArenaState, bytes: usize, align: usize) -> *mut u8 {
+        let layout = Layout::from_size_align(bytes.max(1), align.max(BLOCK_ALIGN)).unwrap();
+        let ptr = unsafe { std::alloc::alloc(layout) };
+        if ptr.is_null() {
+            std::alloc::handle_alloc_error(layout);
+        }
+        state.blocks.push((ptr, layout));
+        self.mem_alloc
+            .fetch_add(layout.size(), std::sync::atomic::Ordering::SeqCst);
+        ptr
+    }
+}
+
+impl MemAllocator for BlockArenaInner {
+    unsafe fn allocate(&self, layout: Layout) -> *mut u8 {
+        let mut state = self.state.lock().unwrap();
+
+        let cmod = (state.alloc_ptr as usize) & (layout.align() - 1);
+        let slop = if cmod == 0 { 0 } else { layout.align() - cmod };
+        let needed = layout.size() + slop;
+        if needed <= state.alloc_bytes_remaining {
+            let result = state.alloc_ptr.add(slop);
+            state.alloc_ptr = state.alloc_ptr.add(needed);
+            state.alloc_bytes_remaining -= needed;
+            return result;
+        }
+
+        self.allocate_fallback(&mut state, layout)
+    }
+
+    fn mem_usage(&self) -> usize {
+        self.mem_alloc.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
+
+impl Drop for BlockArenaInner {
+    fn drop(&mut self) {
+        unsafe {
+            for (ptr, layout) in self.state.get_mut().unwrap().blocks.iter() {
+                std::alloc::dealloc(*ptr, *layout);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::Layout;
+
+    use crate::mem::skl::SkipList;
+
+    use super::{BlockArena, MemAllocator};
+
+    fn fill(arena: &BlockArena, count: usize, size: usize) {
+        let layout = Layout::from_size_align(size, 8).unwrap();
+        for _ in 0..count {
+            let ptr = unsafe { arena.allocate(layout) };
+            assert_eq!(ptr as usize % 8, 0);
+            // every allocation must be writable without touching the others
+            unsafe { std::ptr::write_bytes(ptr, 0xab, size) };
+        }
+    }
+
+    #[test]
+    fn small_allocations_share_blocks() {
+        let arena = BlockArena::new();
+        fill(&arena, 100, 24);
+        assert_eq!(arena.block_count(), 1);
+        assert_eq!(arena.mem_usage(), 4096);
+    }
+
+    #[test]
+    fn larger_block_size_for_medium_objects() {
+        let default = BlockArena::new();
+        fill(&default, 1000, 2000);
+        // every object is over a quarter block, each one gets its own block
+        assert_eq!(default.block_count(), 1000);
+
+        let large = BlockArena::with_block_size(64 << 10);
+        fill(&large, 1000, 2000);
+        // 32 objects per block
+        assert_eq!(large.block_count(), 32);
+    }
+
+    #[test]
+    fn skiplist_on_arena() {
+        let list = SkipList::new(BlockArena::with_block_size(1 << 16));
+        for i in 0..1000 {
+            list.insert(i);
+        }
+        assert!((0..1000).all(|i| list.contains(&i)));
+        assert!(!list.contains(&1000));
+    }
+}
@@ -4,7 +4,7 @@ use std::{
     ops::Index,
     sync::{
         atomic::{AtomicPtr, AtomicUsize, Ordering::*},
-        Arc,
+        Arc, Mutex,
     },
 };
 
@@ -12,6 +12,7 @@ use crate::error::DBResult;
 
 use super::allocator::MemAllocator;
 
+// the largest height a skiplist can be built with, and the default
 const MAX_HEIGHT: usize = 12;
 
 #[repr(C)]
@@ -57,7 +58,7 @@ where
 
         let node = &mut *ptr;
         std::ptr::write(&mut node.key, key);
-        std::ptr::write_bytes(&mut node.next, 0, height);
+        std::ptr::write_bytes(node.next.as_mut_ptr(), 0, height);
         ptr
     }
 
@@ -67,15 +68,21 @@ where
         let ptr = allocator.allocate(layout) as *mut Self;
 
         let node = &mut *ptr;
-        std::ptr::write_bytes(&mut node.next, 0, height);
+        std::ptr::write_bytes(node.next.as_mut_ptr(), 0, height);
         ptr
     }
 }
 
+type HeightGenerator = Box<dyn FnMut() -> usize + Send>;
+
 pub struct SkipList<K: Ord, A: MemAllocator> {
     head: AtomicPtr<Node<K>>,
     allocator: A,
+    // the height of the tallest node, starts at 1 and never shrinks
     max_height: AtomicUsize,
+    height_limit: usize,
+    // only set by `with_rng`, inserts otherwise draw from the thread rng without a lock
+    height_gen: Option<Mutex<HeightGenerator>>,
 }
 
 impl<K, A> Default for SkipList<K, A>
@@ -94,15 +101,44 @@ where
     A: MemAllocator,
 {
     pub fn new(allocator: A) -> Self {
+        Self::new_with(allocator, MAX_HEIGHT, None)
+    }
+
+    /// Create a skiplist whose node heights come from `rng` instead of the
+    /// thread rng, heights are clamped to `[1, MAX_HEIGHT]`.
+    pub fn with_rng(allocator: A, rng: impl FnMut() -> usize + Send + 'static) -> Self {
+        Self::new_with(allocator, MAX_HEIGHT, Some(Mutex::new(Box::new(rng))))
+    }
+
+    /// Create a skiplist whose nodes are at most `max_height` high. Each level holds
+    /// about a quarter of the nodes of the one below, so a list of n keys searches
+    /// best with a height around log4(n); lower heights save memory on small lists.
+    pub fn with_max_height(allocator: A, max_height: usize) -> Self {
+        Self::new_with(allocator, max_height, None)
+    }
+
+    fn new_with(
+        allocator: A,
+        height_limit: usize,
+        height_gen: Option<Mutex<HeightGenerator>>,
+    ) -> Self {
+        assert!(
+            (1..=MAX_HEIGHT).contains(&height_limit),
+            "max height {} not in [1, {}]",
+            height_limit,
+            MAX_HEIGHT
+        );
+
         unsafe {
-            let head = Node::<K>::new_head(&allocator, MAX_HEIGHT);
+            let head = Node::<K>::new_head(&allocator, height_limit);
 
-            let this = Self {
+            Self {
                 head: AtomicPtr::new(head),
                 allocator,
                 max_height: AtomicUsize::new(1),
-            };
-            this
+                height_limit,
+                height_gen,
+            }
         }
     }
 
@@ -114,7 +150,7 @@ where
             assert!(node.is_null() || (*node).key != key);
 
             // update new height
-            let height = random_height();
+            let height = self.random_height();
             let cur_max_height = self.max_height();
             if height > cur_max_height {
                 for i in cur_max_height..height {
@@ -137,14 +173,45 @@ where
     }
 
     pub fn contains(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Get the stored key equal to `key`, it lives as long as the skiplist.
+    pub fn get(&self, key: &K) -> Option<&K> {
         let node = self.find_greater_or_equal(key, None);
-        unsafe { !node.is_null() && &(*node).key == key }
+        unsafe {
+            if !node.is_null() && &(*node).key == key {
+                Some(&(*node).key)
+            } else {
+                None
+            }
+        }
     }
 
     fn max_height(&self) -> usize {
         self.max_height.load(Acquire)
     }
 
+    // searches start from here
+    fn top_level(&self) -> usize {
+        let height = self.max_height();
+        debug_assert!(
+            (1..=self.height_limit).contains(&height),
+            "skiplist height {} not in [1, {}]",
+            height,
+            self.height_limit
+        );
+        height - 1
+    }
+
+    fn random_height(&self) -> usize {
+        let height = match &self.height_gen {
+            Some(height_gen) => (height_gen.lock().unwrap())(),
+            None => random_height(),
+        };
+        height.clamp(1, self.height_limit)
+    }
+
     fn find_greater_or_equal(
         &self,
         key: &K,
@@ -152,7 +219,7 @@ where
     ) -> *mut Node<K> {
         unsafe {
             let mut cur = self.head.load(Relaxed);
-            let mut level = self.max_height() - 1;
+            let mut level = self.top_level();
 
             loop {
                 let next = (*cur).next(level);
@@ -174,7 +241,7 @@ where
     fn find_less_than(&self, key: &K) -> *mut Node<K> {
         unsafe {
             let mut cur = self.head.load(Relaxed);
-            let mut level = self.max_height() - 1;
+            let mut level = self.top_level();
 
             loop {
                 let next = (*cur).next(level);
@@ -193,7 +260,7 @@ where
     fn find_last(&self) -> *mut Node<K> {
         unsafe {
             let mut cur = self.head.load(Relaxed);
-            let mut level = self.max_height() - 1;
+            let mut level = self.top_level();
             loop {
                 let next = (*cur).next(level);
                 if !next.is_null() {
@@ -220,7 +287,8 @@ where
 {
     fn drop(&mut self) {
         unsafe {
-            let mut cur = self.head.load(Relaxed);
+            // the head key is never initialized, skip it
+            let mut cur = (*self.head.load(Relaxed)).next(0);
             while !cur.is_null() {
                 let next = (*cur).next(0);
                 std::ptr::drop_in_place(cur);
@@ -324,11 +392,80 @@ where
 
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
+    use std::sync::{Arc, Mutex};
 
     use crate::mem::{allocator::DefaultAllocator, skl::SklIter};
 
-    use super::SkipList;
+    use super::{SkipList, MAX_HEIGHT};
+
+    fn check_search(list: SkipList<i32, DefaultAllocator>) {
+        for i in (0..1000).map(|i| i * 2) {
+            list.insert(i);
+        }
+        for i in 0..2000 {
+            assert_eq!(list.contains(&i), i % 2 == 0);
+        }
+
+        let mut iter = SklIter::new(Arc::new(list));
+        iter.seek(&999);
+        assert_eq!(iter.peek(), Some(&1000));
+        iter.seek_to_last();
+        assert_eq!(iter.peek(), Some(&1998));
+    }
+
+    #[test]
+    fn all_max_height() {
+        let list = SkipList::with_rng(DefaultAllocator::default(), || MAX_HEIGHT);
+        check_search(list);
+    }
+
+    #[test]
+    fn all_min_height() {
+        let list = SkipList::with_rng(DefaultAllocator::default(), || 1);
+        check_search(list);
+    }
+
+    #[test]
+    fn clamp_height() {
+        let mut heights = [0, usize::MAX].into_iter().cycle();
+        let list = SkipList::with_rng(DefaultAllocator::default(), move || heights.next().unwrap());
+        check_search(list);
+    }
+
+    #[test]
+    fn small_max_height() {
+        for max_height in [1, 2, 3] {
+            let list = SkipList::with_max_height(DefaultAllocator::default(), max_height);
+            check_search(list);
+
+            let list = SkipList::with_max_height(DefaultAllocator::default(), max_height);
+            for i in (0..5000).rev() {
+                list.insert(i);
+            }
+            assert!(list.max_height() <= max_height);
+            let mut iter = SklIter::new(Arc::new(list));
+            iter.seek_to_first();
+            for i in 0..5000 {
+                assert_eq!(iter.peek(), Some(&i));
+                iter.next();
+            }
+            assert!(!iter.is_valid());
+        }
+
+        // heights from the rng are clamped to the list's own limit
+        let list = SkipList::new_with(
+            DefaultAllocator::default(),
+            2,
+            Some(Mutex::new(Box::new(|| MAX_HEIGHT))),
+        );
+        check_search(list);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_max_height() {
+        SkipList::<i32, _>::with_max_height(DefaultAllocator::default(), 0);
+    }
 
     #[test]
     fn insert_some() {
@@ -341,6 +478,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn get_stored_entry() {
+        #[derive(Debug)]
+        struct Entry(i32, String);
+
+        impl PartialEq for Entry {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+
+        impl Eq for Entry {}
+
+        impl PartialOrd for Entry {
+            fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl Ord for Entry {
+            fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+                self.0.cmp(&other.0)
+            }
+        }
+
+        let list = SkipList::new(DefaultAllocator::default());
+        for i in 0..100 {
+            list.insert(Entry(i * 2, format!("value{}", i)));
+        }
+        for i in 0..100 {
+            let entry = list.get(&Entry(i * 2, String::new())).unwrap();
+            assert_eq!(entry.1, format!("value{}", i));
+            assert!(list.get(&Entry(i * 2 + 1, String::new())).is_none());
+        }
+    }
+
     #[test]
     fn iterator() {
         let list = SkipList::new(DefaultAllocator::default());
@@ -37,9 +37,20 @@
 pub mod reader;
 pub mod writer;
 
+use crate::error::{Corruption, CorruptionKind, DBError};
+
 pub const HEADER_SIZE: usize = 7;
 pub const BLOCK_SIZE: usize = 32 * 1024;
 
+/// The crc32 of a record covers its length, type and data, `header` is the first
+/// 3 bytes of the record.
+pub(crate) fn record_crc(header: &[u8], data: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&header[..3]);
+    hasher.update(data);
+    hasher.finalize()
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum RecordType {
     Full = 1,
@@ -48,14 +59,41 @@ pub enum RecordType {
     Last = 4,
 }
 
-impl From<u8> for RecordType {
-    fn from(value: u8) -> Self {
+impl TryFrom<u8> for RecordType {
+    type Error = DBError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
         match value {
-            1 => RecordType::Full,
-            2 => RecordType::First,
-            3 => RecordType::Mid,
-            4 => RecordType::Last,
-            _ => panic!("Invalid record type, found number: {}", value),
+            1 => Ok(RecordType::Full),
+            2 => Ok(RecordType::First),
+            3 => Ok(RecordType::Mid),
+            4 => Ok(RecordType::Last),
+            _ => Err(Corruption::new(
+                CorruptionKind::BadType,
+                format!("invalid record type, found number: {}", value),
+            )
+            .into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RecordType;
+
+    #[test]
+    fn stable_record_type() {
+        let types = [
+            (RecordType::Full, 1),
+            (RecordType::First, 2),
+            (RecordType::Mid, 3),
+            (RecordType::Last, 4),
+        ];
+        for (ty, n) in types {
+            assert_eq!(ty as u8, n);
+            assert_eq!(RecordType::try_from(n).unwrap() as u8, n);
         }
+        assert!(RecordType::try_from(0).is_err());
+        assert!(RecordType::try_from(5).is_err());
     }
 }
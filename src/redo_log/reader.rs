@@ -1,10 +1,14 @@
-use std::vec;
-
-use bytes::Buf;
+use std::{path::PathBuf, sync::Arc};
 
 use crate::{
-    error::DBResult,
-    redo_log::{BLOCK_SIZE, HEADER_SIZE},
+    config::ReadOptions,
+    error::{Corruption, CorruptionKind, DBError, DBResult},
+    io::IoBackend,
+    redo_log::{record_crc, BLOCK_SIZE, HEADER_SIZE},
+    utils::{
+        buffer_pool::{BufferPool, PooledBuf},
+        encoding,
+    },
 };
 
 use super::RecordType;
@@ -14,77 +18,124 @@ struct Record {
     data: Vec<u8>,
 }
 
+/// Receives what the reader skips. A corrupted block or record is reported as a
+/// [`Corruption`] at its file offset, naming the file given to
+/// [`LogReader::in_file`], a failed read as the io error.
 pub trait ErrorReporter {
     fn report(&mut self, read_pos: usize, err: Box<dyn std::error::Error>);
 }
 
 pub struct LogReader<'a> {
     fd: &'a std::fs::File,
-    ring: rio::Rio,
+    io: Arc<dyn IoBackend>,
     reporter: Option<Box<dyn ErrorReporter>>,
-    data: Vec<u8>,
+    // named by reported corruptions
+    path: Option<PathBuf>,
+    data: PooledBuf,
 
     read_offset: usize,
     read_rec_err: bool,
+    // started mid-file, skip fragments until a record begins
+    resyncing: bool,
 }
 
 impl<'a> LogReader<'a> {
     pub fn new(
         fd: &'a std::fs::File,
-        ring: rio::Rio,
+        io: Arc<dyn IoBackend>,
+        reporter: Option<Box<dyn ErrorReporter>>,
+    ) -> DBResult<Self> {
+        Self::with_buffer_pool(fd, io, reporter, BufferPool::new(1))
+    }
+
+    /// Create a reader whose read buffer is taken from `pool`, so readers
+    /// opened one after another (e.g. replaying several logs) reuse it.
+    pub fn with_buffer_pool(
+        fd: &'a std::fs::File,
+        io: Arc<dyn IoBackend>,
         reporter: Option<Box<dyn ErrorReporter>>,
+        pool: Arc<BufferPool>,
     ) -> DBResult<Self> {
         Ok(Self {
             fd,
-            ring,
+            io,
             reporter,
-            data: vec![],
+            path: None,
+            data: pool.get(0),
             read_offset: 0,
             read_rec_err: false,
+            resyncing: false,
         })
     }
 
-    // if some block error, report it, and skip taill
-    fn read_blocks(&mut self) -> DBResult<()> {
-        let len = self.fd.metadata()?.len() as usize;
-        let mut buf = vec![0_u8; len];
+    /// Create a reader that starts at the block aligned `start_offset`, the
+    /// tail of a record that began in an earlier block is skipped.
+    pub fn new_at(
+        fd: &'a std::fs::File,
+        io: Arc<dyn IoBackend>,
+        reporter: Option<Box<dyn ErrorReporter>>,
+        start_offset: usize,
+    ) -> DBResult<Self> {
+        if start_offset % BLOCK_SIZE != 0 {
+            return Err(DBError::InvalidArgument(format!(
+                "start offset {} is not block aligned",
+                start_offset
+            )));
+        }
+        let mut this = Self::new(fd, io, reporter)?;
+        this.read_offset = start_offset;
+        this.resyncing = start_offset > 0;
+        Ok(this)
+    }
 
-        let block_count = (len + BLOCK_SIZE - 1) / BLOCK_SIZE;
-        let mut chunks = Vec::with_capacity(block_count);
-        let mut comps = Vec::with_capacity(block_count);
+    /// Name `path` as the log file in every reported corruption.
+    pub fn in_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
 
-        for i in 0..block_count {
-            let l = i * BLOCK_SIZE;
-            let r = ((i + 1) * BLOCK_SIZE).min(len);
-            // let chunk = &buf[l..r];
-            let ptr = buf.as_mut_ptr();
-            let chunk = unsafe { std::slice::from_raw_parts_mut(ptr.add(l), r - l) };
-            chunks.push((chunk, l, r - l));
-        }
-        for (chunk, offset, len) in chunks.iter_mut() {
-            let comp = self.ring.read_at(self.fd, chunk, *offset as u64);
-            comps.push((comp, *len, *offset));
+    fn corruption(&self, c: Corruption, offset: usize) -> Box<dyn std::error::Error> {
+        let c = c.at(offset as u64);
+        match &self.path {
+            Some(path) => Box::new(c.in_file(path)),
+            None => Box::new(c),
         }
+    }
+
+    // if some block error, report it, and skip taill
+    fn read_blocks(&mut self) -> DBResult<()> {
+        let file_len = self.fd.metadata()?.len() as usize;
+        let mut buf = std::mem::take(&mut *self.data);
+        buf.resize(file_len, 0);
+
+        let mut reads: Vec<(&mut [u8], u64)> = buf
+            .chunks_mut(BLOCK_SIZE)
+            .enumerate()
+            .map(|(i, chunk)| (chunk, (i * BLOCK_SIZE) as u64))
+            .collect();
+        let results = self.io.read_many(self.fd, &mut reads);
 
         let mut last = 0;
         let mut have_err = false;
-        for (comp, len, offset) in comps.into_iter() {
-            let res = comp.wait();
+        for (i, res) in results.into_iter().enumerate() {
             if have_err {
-                continue;
+                break;
             }
+            let offset = i * BLOCK_SIZE;
+            let len = BLOCK_SIZE.min(file_len - offset);
 
             match res {
                 Ok(count) => {
                     if count != len {
+                        let err = self.corruption(
+                            Corruption::new(
+                                CorruptionKind::Truncated,
+                                format!("short log block read: {} of {} bytes", count, len),
+                            ),
+                            offset,
+                        );
                         if let Some(r) = &mut self.reporter {
-                            r.report(
-                                offset,
-                                Box::new(std::io::Error::new(
-                                    std::io::ErrorKind::UnexpectedEof,
-                                    "read block not enough",
-                                )),
-                            );
+                            r.report(offset, err);
                             have_err = true;
                             continue;
                         }
@@ -103,16 +154,17 @@ impl<'a> LogReader<'a> {
 
         buf.truncate(last);
 
-        self.data = buf;
+        *self.data = buf;
         Ok(())
     }
 
     // if some record error, report it, and skip tail
-    fn read_raw_record(&mut self) -> Option<Record> {
+    fn read_raw_record(&mut self, verify_checksums: bool) -> Option<Record> {
         macro_rules! report_err {
             ($err:expr) => {
+                let err = $err;
                 if let Some(r) = &mut self.reporter {
-                    r.report(self.read_offset, $err);
+                    r.report(self.read_offset, err);
                 }
                 self.read_rec_err = true;
             };
@@ -128,39 +180,55 @@ impl<'a> LogReader<'a> {
             self.read_offset += block_remain;
         }
 
-        let mut buf = &self.data[self.read_offset..];
-        if buf.len() < 7 {
+        let buf = &self.data[self.read_offset..];
+        if buf.len() < HEADER_SIZE {
             return None;
         }
 
-        let len = buf.get_u16_le() as usize;
-        if len > buf.len() + 4 + 1 {
-            report_err!(Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "record len too long"
-            )));
+        let len = encoding::get_u16(buf) as usize;
+        if len + HEADER_SIZE > self.data.len() - self.read_offset {
+            report_err!(self.corruption(
+                Corruption::new(
+                    CorruptionKind::BadLength,
+                    format!("record length {} runs past the log", len),
+                ),
+                self.read_offset
+            ));
             return None;
         }
 
-        let ty = RecordType::from(buf.get_u8());
-        let data = Vec::from(&buf[..len - 4]);
-        let mut buf = &buf[len - 4..];
-        let crc32 = buf.get_u32_le();
+        let ty = buf[2];
+        let data = Vec::from(&buf[3..3 + len]);
 
-        let crc = crc32fast::hash(&self.data[self.read_offset..self.read_offset + len + 3]);
-        if crc != crc32 {
-            report_err!(Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "crc32 not match"
-            )));
-            return None;
+        if verify_checksums {
+            let crc32 = encoding::get_u32(&buf[3 + len..]);
+            let crc = record_crc(buf, &data);
+            if crc != crc32 {
+                report_err!(self.corruption(
+                    Corruption::new(CorruptionKind::ChecksumMismatch, "record checksum mismatch"),
+                    self.read_offset
+                ));
+                return None;
+            }
         }
 
+        let ty = match RecordType::try_from(ty) {
+            Ok(ty) => ty,
+            Err(DBError::Corruption(c)) => {
+                report_err!(self.corruption(c, self.read_offset));
+                return None;
+            }
+            Err(e) => {
+                report_err!(Box::new(e));
+                return None;
+            }
+        };
+
         self.read_offset += len + HEADER_SIZE;
         Some(Record { ty, data })
     }
 
-    fn read_record(&mut self) -> Option<Record> {
+    fn read_record(&mut self, verify_checksums: bool) -> Option<Record> {
         loop {
             if self.read_offset >= self.data.len() {
                 if let Err(e) = self.read_blocks() {
@@ -170,7 +238,7 @@ impl<'a> LogReader<'a> {
                 }
             }
 
-            let rec = self.read_raw_record();
+            let rec = self.read_raw_record(verify_checksums);
             if rec.is_none() {
                 return None;
             }
@@ -182,14 +250,29 @@ impl<'a> LogReader<'a> {
     }
 
     pub fn read_data(&mut self) -> Option<Vec<u8>> {
+        self.read_data_with_options(&ReadOptions::default())
+    }
+
+    /// Read the next logical record.
+    ///
+    /// See [`ReadOptions::verify_checksums`] before turning checksum
+    /// verification off.
+    pub fn read_data_with_options(&mut self, opts: &ReadOptions) -> Option<Vec<u8>> {
         let mut data = vec![];
         loop {
-            let rec = self.read_record();
+            let rec = self.read_record(opts.verify_checksums);
             let rec = match rec {
                 Some(r) => r,
                 None => return None,
             };
 
+            if self.resyncing {
+                match rec.ty {
+                    RecordType::Mid | RecordType::Last => continue,
+                    RecordType::Full | RecordType::First => self.resyncing = false,
+                }
+            }
+
             match rec.ty {
                 RecordType::Full => {
                     return Some(rec.data);
@@ -208,3 +291,248 @@ impl<'a> LogReader<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        os::unix::fs::FileExt,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+        time::Instant,
+    };
+
+    use bytes::BufMut;
+
+    use crate::{
+        config::ReadOptions,
+        error::{Corruption, CorruptionKind, DBError},
+        io::default_backend,
+        redo_log::{writer::LogWriter, BLOCK_SIZE, HEADER_SIZE},
+        utils::{buffer_pool::BufferPool, testing::TempFile},
+    };
+
+    use super::{ErrorReporter, LogReader};
+
+    struct TestReporter(Arc<Mutex<Vec<usize>>>);
+
+    impl ErrorReporter for TestReporter {
+        fn report(&mut self, read_pos: usize, _err: Box<dyn std::error::Error>) {
+            self.0.lock().unwrap().push(read_pos);
+        }
+    }
+
+    struct FileReporter(Arc<Mutex<Vec<Option<PathBuf>>>>);
+
+    impl ErrorReporter for FileReporter {
+        fn report(&mut self, _read_pos: usize, err: Box<dyn std::error::Error>) {
+            let c = err.downcast_ref::<Corruption>().expect("a corruption");
+            self.0.lock().unwrap().push(c.file.clone());
+        }
+    }
+
+    struct KindReporter(Arc<Mutex<Vec<(CorruptionKind, Option<u64>)>>>);
+
+    impl ErrorReporter for KindReporter {
+        fn report(&mut self, _read_pos: usize, err: Box<dyn std::error::Error>) {
+            let c = err.downcast_ref::<Corruption>().expect("a corruption");
+            self.0.lock().unwrap().push((c.kind, c.offset));
+        }
+    }
+
+    fn write_records(fd: &File, records: &[Vec<u8>]) {
+        let mut writer = LogWriter::new(fd, default_backend());
+        for rec in records {
+            writer.append(rec).unwrap();
+        }
+    }
+
+    #[test]
+    fn read_back_records() {
+        let fd = TempFile::new("read_back_records");
+        let records = vec![
+            b"foo".to_vec(),
+            vec![],
+            vec![7; 100 * 1024],
+            b"bar".to_vec(),
+            vec![],
+        ];
+        write_records(&fd, &records);
+
+        let mut reader = LogReader::new(&fd, default_backend(), None).unwrap();
+        for rec in records.iter() {
+            assert_eq!(reader.read_data().as_ref(), Some(rec));
+        }
+        assert_eq!(reader.read_data(), None);
+    }
+
+    #[test]
+    fn read_from_block_offset() {
+        let fd = TempFile::new("read_from_block_offset");
+        let records = vec![
+            b"foo".to_vec(),
+            vec![1; BLOCK_SIZE * 2],
+            b"bar".to_vec(),
+            vec![2; BLOCK_SIZE],
+            b"baz".to_vec(),
+        ];
+        write_records(&fd, &records);
+
+        // block 1 and 2 start with the mid and last fragments of the second record
+        for block in [1, 2] {
+            let mut reader =
+                LogReader::new_at(&fd, default_backend(), None, block * BLOCK_SIZE).unwrap();
+            for rec in records[2..].iter() {
+                assert_eq!(reader.read_data().as_ref(), Some(rec));
+            }
+            assert_eq!(reader.read_data(), None);
+        }
+
+        let mut reader = LogReader::new_at(&fd, default_backend(), None, 0).unwrap();
+        for rec in records.iter() {
+            assert_eq!(reader.read_data().as_ref(), Some(rec));
+        }
+
+        assert!(matches!(
+            LogReader::new_at(&fd, default_backend(), None, BLOCK_SIZE + 1),
+            Err(DBError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn reuse_read_buffer() {
+        let fd = TempFile::new("reuse_read_buffer");
+        write_records(&fd, &[vec![1; 64 * 1024]]);
+
+        let pool = BufferPool::new(4);
+        for _ in 0..100 {
+            let mut reader =
+                LogReader::with_buffer_pool(&fd, default_backend(), None, pool.clone()).unwrap();
+            assert!(reader.read_data().is_some());
+            assert_eq!(reader.read_data(), None);
+        }
+        assert_eq!(pool.pooled(), 1);
+    }
+
+    #[test]
+    fn skip_checksum_verification() {
+        let fd = TempFile::new("skip_checksum_verification");
+        write_records(&fd, &[b"hello".to_vec()]);
+        fd.write_all_at(b"j", HEADER_SIZE as u64 - 4).unwrap();
+
+        let errors = Arc::new(Mutex::new(vec![]));
+        let reporter = Box::new(TestReporter(errors.clone()));
+        let mut reader = LogReader::new(&fd, default_backend(), Some(reporter)).unwrap();
+        assert_eq!(reader.read_data(), None);
+        assert_eq!(*errors.lock().unwrap(), vec![0]);
+
+        let opts = ReadOptions {
+            verify_checksums: false,
+        };
+        let mut reader = LogReader::new(&fd, default_backend(), None).unwrap();
+        assert_eq!(
+            reader.read_data_with_options(&opts),
+            Some(b"jello".to_vec())
+        );
+    }
+
+    #[test]
+    fn invalid_record_type() {
+        let fd = TempFile::new("invalid_record_type");
+        let mut rec = vec![];
+        rec.put_u16_le(3);
+        rec.put_u8(9);
+        rec.put_slice(b"foo");
+        let crc = crc32fast::hash(&rec);
+        rec.put_u32_le(crc);
+        fd.write_all_at(&rec, 0).unwrap();
+
+        let errors = Arc::new(Mutex::new(vec![]));
+        let reporter = Box::new(TestReporter(errors.clone()));
+        let mut reader = LogReader::new(&fd, default_backend(), Some(reporter)).unwrap();
+        assert_eq!(reader.read_data(), None);
+        assert_eq!(*errors.lock().unwrap(), vec![0]);
+    }
+
+    #[test]
+    fn corruption_kinds() {
+        let read_kinds = |fd: &File| {
+            let kinds = Arc::new(Mutex::new(vec![]));
+            let reporter = Box::new(KindReporter(kinds.clone()));
+            let mut reader = LogReader::new(fd, default_backend(), Some(reporter)).unwrap();
+            while reader.read_data().is_some() {}
+            let kinds = kinds.lock().unwrap().clone();
+            kinds
+        };
+
+        let fd = TempFile::new("corruption_kinds");
+        write_records(&fd, &[b"hello".to_vec(), b"world".to_vec()]);
+        let second = (HEADER_SIZE + 5) as u64;
+        fd.write_all_at(b"j", second + 3).unwrap();
+        assert_eq!(
+            read_kinds(&fd),
+            [(CorruptionKind::ChecksumMismatch, Some(second))]
+        );
+
+        fd.set_len(0).unwrap();
+        write_records(&fd, &[b"hello".to_vec()]);
+        fd.write_all_at(&[0xff, 0xff], 0).unwrap();
+        assert_eq!(read_kinds(&fd), [(CorruptionKind::BadLength, Some(0))]);
+
+        let mut rec = vec![];
+        rec.put_u16_le(3);
+        rec.put_u8(9);
+        rec.put_slice(b"foo");
+        let crc = crc32fast::hash(&rec);
+        rec.put_u32_le(crc);
+        fd.set_len(0).unwrap();
+        fd.write_all_at(&rec, 0).unwrap();
+        assert_eq!(read_kinds(&fd), [(CorruptionKind::BadType, Some(0))]);
+    }
+
+    #[test]
+    fn corruption_names_file() {
+        let fd = TempFile::new("log_corruption_names_file");
+        write_records(&fd, &[b"hello".to_vec()]);
+        fd.write_all_at(b"j", 3).unwrap();
+
+        let files = Arc::new(Mutex::new(vec![]));
+        let reporter = Box::new(FileReporter(files.clone()));
+        let mut reader = LogReader::new(&fd, default_backend(), Some(reporter))
+            .unwrap()
+            .in_file(fd.path());
+        assert_eq!(reader.read_data(), None);
+        assert_eq!(*files.lock().unwrap(), [Some(fd.path().to_path_buf())]);
+    }
+
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_skip_checksum_verification() {
+        const COUNT: usize = 64;
+
+        let fd = TempFile::new("bench_skip_checksum_verification");
+        let records = vec![vec![42; 1024 * 1024]; COUNT];
+        write_records(&fd, &records);
+
+        let read_all = |verify_checksums| {
+            let opts = ReadOptions { verify_checksums };
+            let mut reader = LogReader::new(&fd, default_backend(), None).unwrap();
+            // load the file first, so only record decoding is timed
+            assert!(reader.read_data_with_options(&opts).is_some());
+            let start = Instant::now();
+            for _ in 1..COUNT {
+                assert!(reader.read_data_with_options(&opts).is_some());
+            }
+            start.elapsed()
+        };
+
+        let verified = read_all(true);
+        let unverified = read_all(false);
+        assert!(
+            unverified < verified,
+            "verified: {:?}, unverified: {:?}",
+            verified,
+            unverified
+        );
+    }
+}
@@ -1,16 +1,30 @@
+use std::{sync::Arc, time::Instant};
+
 use bytes::BufMut;
 
 use crate::{
-    error::DBResult,
-    redo_log::{RecordType, BLOCK_SIZE, HEADER_SIZE},
+    config::SyncPolicy,
+    error::{DBError, DBResult},
+    io::IoBackend,
+    redo_log::{record_crc, RecordType, BLOCK_SIZE, HEADER_SIZE},
+    utils::{
+        clock::{Clock, SystemClock},
+        encoding,
+    },
 };
 
 pub struct LogWriter<'f> {
     fd: &'f std::fs::File,
     file_offset: u64,
     block_offset: u64,
-    ring: rio::Rio,
+    io: Arc<dyn IoBackend>,
     buf: Vec<u8>,
+
+    sync_policy: SyncPolicy,
+    writes_since_sync: usize,
+    last_sync: Instant,
+    sync_count: u64,
+    clock: Arc<dyn Clock>,
 }
 
 const EMPTY: [&[u8]; 8] = [
@@ -25,21 +39,47 @@ const EMPTY: [&[u8]; 8] = [
 ];
 
 impl<'f> LogWriter<'f> {
-    pub fn new(fd: &'f std::fs::File, ring: rio::Rio) -> Self {
+    pub fn new(fd: &'f std::fs::File, io: Arc<dyn IoBackend>) -> Self {
+        Self::with_sync_policy(fd, io, SyncPolicy::Never)
+    }
+
+    pub fn with_sync_policy(
+        fd: &'f std::fs::File,
+        io: Arc<dyn IoBackend>,
+        policy: SyncPolicy,
+    ) -> Self {
+        Self::with_clock(fd, io, policy, Arc::new(SystemClock))
+    }
+
+    /// Create a writer that measures `SyncPolicy::Interval` with `clock`.
+    pub fn with_clock(
+        fd: &'f std::fs::File,
+        io: Arc<dyn IoBackend>,
+        policy: SyncPolicy,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
         Self {
             fd,
             file_offset: 0,
             block_offset: 0,
-            ring,
+            io,
             buf: vec![],
+
+            sync_policy: policy,
+            writes_since_sync: 0,
+            last_sync: clock.now(),
+            sync_count: 0,
+            clock,
         }
     }
 
+    // all fragments and block paddings of a record are written with a single write
     pub fn append(&mut self, data: &[u8]) -> DBResult<()> {
         let mut begin = true;
         let mut data_offset = 0;
         loop {
-            if data_offset >= data.len() {
+            // an empty record is still written as one full fragment
+            if !begin && data_offset >= data.len() {
                 break;
             }
 
@@ -47,7 +87,6 @@ impl<'f> LogWriter<'f> {
             if remain < HEADER_SIZE {
                 if remain > 0 {
                     self.buf.put_slice(EMPTY[remain]);
-                    self.write_buf()?;
                 }
                 self.block_offset = 0;
             }
@@ -68,28 +107,216 @@ impl<'f> LogWriter<'f> {
             };
 
             let buf = &mut self.buf;
+            let start = buf.len();
             let data_len = (r - l) as u16;
-            buf.put_u16_le(data_len);
+            encoding::put_u16(buf, data_len);
             buf.put_u8(ty as u8);
+            let crc = record_crc(&buf[start..], &data[l..r]);
             buf.put_slice(&data[l..r]);
-            let crc = crc32fast::hash(&buf[..]);
-            buf.put_u32_le(crc);
+            encoding::put_u32(buf, crc);
 
-            self.write_buf()?;
+            self.block_offset += (HEADER_SIZE + r - l) as u64;
 
             data_offset = r;
             begin = false;
         }
+        self.write_buf()?;
+
+        self.writes_since_sync += 1;
+        let need_sync = match self.sync_policy {
+            SyncPolicy::Never => false,
+            SyncPolicy::EveryWrite => true,
+            SyncPolicy::EveryN(n) => self.writes_since_sync >= n,
+            SyncPolicy::Interval(interval) => {
+                self.clock.now().duration_since(self.last_sync) >= interval
+            }
+        };
+        if need_sync {
+            self.sync()?;
+        }
+
+        Ok(())
+    }
 
+    pub fn sync(&mut self) -> DBResult<()> {
+        self.io.fsync(self.fd)?;
+        self.writes_since_sync = 0;
+        self.last_sync = self.clock.now();
+        self.sync_count += 1;
         Ok(())
     }
 
+    pub fn sync_count(&self) -> u64 {
+        self.sync_count
+    }
+
     fn write_buf(&mut self) -> DBResult<()> {
         let buf = &self.buf;
-        let comp = self.ring.write_at(self.fd, &buf, self.file_offset);
-        let count = comp.wait()?;
-        assert_eq!(buf.len(), count);
+        let res = self.io.write_at(self.fd, buf, self.file_offset);
+        let len = buf.len();
         self.buf.clear();
+
+        let count = res?;
+        self.file_offset += count as u64;
+        if count != len {
+            return Err(DBError::IO(std::io::Error::new(
+                std::io::ErrorKind::WriteZero,
+                format!("short log write: {} of {} bytes", count, len),
+            )));
+        }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::fs::FileExt, sync::Arc, time::Duration};
+
+    use crate::{
+        config::SyncPolicy,
+        io::{default_backend, StdBackend},
+        redo_log::{reader::LogReader, BLOCK_SIZE, HEADER_SIZE},
+        utils::{clock::MockClock, testing::TempFile},
+    };
+
+    use super::LogWriter;
+
+    fn count_syncs(name: &str, policy: SyncPolicy, writes: usize) -> u64 {
+        let fd = TempFile::new(name);
+        let mut writer = LogWriter::with_sync_policy(&fd, default_backend(), policy);
+        for _ in 0..writes {
+            writer.append(b"record").unwrap();
+        }
+        writer.sync_count()
+    }
+
+    #[test]
+    fn sync_policy() {
+        assert_eq!(count_syncs("sync_never", SyncPolicy::Never, 10), 0);
+        assert_eq!(
+            count_syncs("sync_every_write", SyncPolicy::EveryWrite, 10),
+            10
+        );
+        assert_eq!(count_syncs("sync_every_n", SyncPolicy::EveryN(3), 10), 3);
+        assert_eq!(
+            count_syncs("sync_every_n_exact", SyncPolicy::EveryN(5), 10),
+            2
+        );
+        let interval = SyncPolicy::Interval(Duration::from_secs(3600));
+        assert_eq!(count_syncs("sync_interval", interval, 10), 0);
+        let interval = SyncPolicy::Interval(Duration::ZERO);
+        assert_eq!(count_syncs("sync_interval_zero", interval, 10), 10);
+    }
+
+    #[test]
+    fn sync_interval_follows_clock() {
+        let fd = TempFile::new("sync_interval_follows_clock");
+        let clock = Arc::new(MockClock::new());
+        let policy = SyncPolicy::Interval(Duration::from_secs(60));
+        let mut writer = LogWriter::with_clock(&fd, default_backend(), policy, clock.clone());
+
+        writer.append(b"record").unwrap();
+        clock.advance(Duration::from_secs(59));
+        writer.append(b"record").unwrap();
+        assert_eq!(writer.sync_count(), 0);
+
+        clock.advance(Duration::from_secs(1));
+        writer.append(b"record").unwrap();
+        assert_eq!(writer.sync_count(), 1);
+        writer.append(b"record").unwrap();
+        assert_eq!(writer.sync_count(), 1);
+    }
+
+    #[test]
+    fn crc_covers_header_and_data() {
+        let fd = TempFile::new("crc_covers_header_and_data");
+        let records: [&[u8]; 4] = [b"foo", b"", b"hello world", &[0xff; 300]];
+        let mut writer = LogWriter::new(&fd, default_backend());
+        for rec in records {
+            writer.append(rec).unwrap();
+        }
+
+        let len = fd.metadata().unwrap().len() as usize;
+        let mut buf = vec![0; len];
+        fd.read_exact_at(&mut buf, 0).unwrap();
+        let mut offset = 0;
+        for rec in records {
+            let end = offset + 3 + rec.len();
+            assert_eq!(&buf[offset + 3..end], rec);
+            let crc = crc32fast::hash(&buf[offset..end]);
+            assert_eq!(buf[end..end + 4], crc.to_le_bytes());
+            offset = end + 4;
+        }
+        assert_eq!(offset, len);
+
+        let mut reader = LogReader::new(&fd, default_backend(), None).unwrap();
+        for rec in records {
+            assert_eq!(reader.read_data().unwrap(), rec);
+        }
+        assert_eq!(reader.read_data(), None);
+    }
+
+    #[test]
+    fn header_is_little_endian() {
+        let fd = TempFile::new("header_is_little_endian");
+        let mut writer = LogWriter::new(&fd, default_backend());
+        writer.append(&[7; 0x102]).unwrap();
+
+        let mut rec = vec![0; HEADER_SIZE + 0x102];
+        fd.read_exact_at(&mut rec, 0).unwrap();
+        // | len 2b | type 1b | data | crc 4b |
+        assert_eq!(rec[..2], [0x02, 0x01]);
+        let crc = crc32fast::hash(&rec[..3 + 0x102]);
+        assert_eq!(rec[3 + 0x102..], crc.to_le_bytes());
+    }
+
+    #[test]
+    fn span_blocks() {
+        let fd = TempFile::new("span_blocks");
+        let records = [
+            // leaves less than a header in the first block
+            vec![1; BLOCK_SIZE - HEADER_SIZE - 3],
+            vec![2; BLOCK_SIZE * 2],
+            vec![3; 10],
+        ];
+        let mut writer = LogWriter::new(&fd, default_backend());
+        for rec in records.iter() {
+            writer.append(rec).unwrap();
+        }
+        // padding 3, first 1 + mid 1 + last 1 headers, full 1 header
+        let headers = 1 + 3 + 1;
+        let len = records.iter().map(|r| r.len()).sum::<usize>() + 3 + headers * HEADER_SIZE;
+        assert_eq!(fd.metadata().unwrap().len(), len as u64);
+
+        let mut reader = LogReader::new(&fd, default_backend(), None).unwrap();
+        for rec in records.iter() {
+            assert_eq!(reader.read_data().as_ref(), Some(rec));
+        }
+        assert_eq!(reader.read_data(), None);
+    }
+
+    #[test]
+    fn std_backend_round_trip() {
+        let fd = TempFile::new("std_backend_round_trip");
+        let records: Vec<Vec<u8>> = (0..50)
+            .map(|i| vec![i as u8; i * BLOCK_SIZE / 10])
+            .collect();
+        let mut writer =
+            LogWriter::with_sync_policy(&fd, Arc::new(StdBackend), SyncPolicy::EveryWrite);
+        for rec in records.iter() {
+            writer.append(rec).unwrap();
+        }
+        assert_eq!(writer.sync_count(), records.len() as u64);
+
+        let mut reader = LogReader::new(&fd, Arc::new(StdBackend), None).unwrap();
+        for rec in records.iter() {
+            assert_eq!(reader.read_data().as_ref(), Some(rec));
+        }
+        assert_eq!(reader.read_data(), None);
+
+        // the log format does not depend on the backend
+        let mut reader = LogReader::new(&fd, default_backend(), None).unwrap();
+        assert_eq!(reader.read_data().as_ref(), Some(&records[0]));
+        assert_eq!(reader.read_data().as_ref(), Some(&records[1]));
+    }
+}
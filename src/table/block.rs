@@ -1,12 +1,12 @@
 use std::{cmp::Ordering, sync::Arc};
 
-use bytes::{Buf, Bytes};
+use bytes::Bytes;
 
 use crate::{
     comparator::Comparator,
-    error::{DBError, DBResult},
+    error::{Corruption, CorruptionKind, DBError, DBResult},
     iterator::Iterator,
-    utils::varint::VarInt,
+    utils::{encoding, varint::VarInt},
 };
 
 use super::SIZE_U32;
@@ -14,41 +14,93 @@ use super::SIZE_U32;
 pub struct Block {
     data: Bytes,
     restart_offset: u32,
-    restart_count: u32,
+    restarts: Arc<[u32]>,
 }
 
 impl Block {
     pub fn new(data: Bytes) -> DBResult<Self> {
         if data.len() < 4 {
-            return Err(DBError::Corruption("block data too short".into()));
+            return Err(Corruption::new(CorruptionKind::Truncated, "block data too short").into());
         }
 
         let max_restarts_allowed = (data.len() - SIZE_U32) / SIZE_U32;
         let restart_count = Self::get_restart_count(&data);
 
-        if restart_count as usize <= max_restarts_allowed {
-            let restart_offset = data.len() as u32 - (1 + restart_count) * SIZE_U32 as u32;
-            return Ok(Self {
-                data,
-                restart_offset,
-                restart_count,
-            });
+        if restart_count as usize > max_restarts_allowed {
+            return Err(Corruption::new(CorruptionKind::BadLength, "block data invalid").into());
         }
 
-        return Err(DBError::Corruption("block data invalid".into()));
+        let restart_offset = data.len() as u32 - (1 + restart_count) * SIZE_U32 as u32;
+        let restarts = Self::parse_restart_points(&data, restart_offset, restart_count)?;
+        Ok(Self {
+            data,
+            restart_offset,
+            restarts,
+        })
+    }
+
+    // every restart point must be ascending and point at an entry inside the data region,
+    // an empty block only has the restart point 0. Seeking decodes the key at a restart
+    // point without the keys before it, so it must not share a prefix with them; that
+    // holds for any restart interval the block was built with.
+    fn parse_restart_points(
+        data: &[u8],
+        restart_offset: u32,
+        restart_count: u32,
+    ) -> DBResult<Arc<[u32]>> {
+        let mut restarts = Vec::with_capacity(restart_count as usize);
+        let mut last = 0;
+        for i in 0..restart_count as usize {
+            let point = encoding::get_u32(&data[restart_offset as usize + i * SIZE_U32..]);
+            let in_range = point < restart_offset || (point == 0 && restart_offset == 0);
+            if !in_range || point < last {
+                return Err(Corruption::new(
+                    CorruptionKind::BadOffset,
+                    format!("bad restart point {}: {}", i, point),
+                )
+                .into());
+            }
+            if point < restart_offset {
+                let shared = VarInt::from_varint(&data[point as usize..restart_offset as usize]);
+                if !matches!(shared, Ok((0u32, _))) {
+                    return Err(Corruption::new(
+                        CorruptionKind::BadEntry,
+                        format!("restart point {} shares a key prefix: {}", i, point),
+                    )
+                    .into());
+                }
+            }
+            last = point;
+            restarts.push(point);
+        }
+        Ok(restarts.into())
     }
 
     pub fn iter(&self, comp: Arc<dyn Comparator>) -> BlockIter {
         BlockIter::new(
             self.data.clone(),
             self.restart_offset,
-            self.restart_count,
+            self.restarts.clone(),
             comp,
         )
     }
 
+    /// The smallest key of the block, None if it is empty or its first entry is corrupted.
+    pub fn first_key(&self, comp: Arc<dyn Comparator>) -> Option<Bytes> {
+        let mut iter = self.iter(comp);
+        iter.seek_to_first();
+        iter.is_valid().then(|| Bytes::copy_from_slice(iter.key()))
+    }
+
+    /// The largest key of the block, None if it is empty or its last restart run is corrupted.
+    pub fn last_key(&self, comp: Arc<dyn Comparator>) -> Option<Bytes> {
+        let mut iter = self.iter(comp);
+        iter.seek_to_last();
+        iter.is_valid().then(|| Bytes::copy_from_slice(iter.key()))
+    }
+
     fn get_restart_count(data: &[u8]) -> u32 {
-        (&data[data.len() - SIZE_U32..]).get_u32_le()
+        encoding::get_u32(&data[data.len() - SIZE_U32..])
     }
 }
 
@@ -58,6 +110,8 @@ pub struct BlockIter {
 
     restart_offset: u32,
     restart_count: u32,
+    // parsed once by the block, so seeking does not decode the restart array again
+    restarts: Arc<[u32]>,
 
     // current
     current: u32,
@@ -77,17 +131,19 @@ impl BlockIter {
     fn new(
         data: Bytes,
         restart_offset: u32,
-        restart_count: u32,
+        restarts: Arc<[u32]>,
         comp: Arc<dyn Comparator>,
     ) -> Self {
+        let restart_count = restarts.len() as u32;
         let this = Self {
             comparator: comp,
             data,
 
             restart_offset,
             restart_count,
+            restarts,
 
-            current: restart_count, // invalid status
+            current: restart_offset, // invalid status
             restart_index: 0,
 
             key: Vec::new(),
@@ -106,8 +162,26 @@ impl BlockIter {
     }
 
     fn get_restart_point(&self, index: u32) -> u32 {
-        assert!(index < self.restart_count);
-        (&self.data[self.restart_offset as usize + index as usize * SIZE_U32..]).get_u32_le()
+        self.restarts[index as usize]
+    }
+
+    // keys at restart points are stored in full, returns the key's offset and length,
+    // None if the entry can not be decoded inside the data region
+    fn restart_key(&self, index: u32) -> Option<(usize, usize)> {
+        let limit = self.restart_offset as usize;
+        let mut offset = self.get_restart_point(index) as usize;
+        let mut lens = [0; 3];
+        for len in lens.iter_mut() {
+            let (n, next): (u32, _) = VarInt::from_varint(&self.data[offset..limit]).ok()?;
+            *len = n;
+            offset += next;
+        }
+
+        let [shared_len, non_shared_len, _value_len] = lens;
+        if shared_len != 0 || offset + non_shared_len as usize > limit {
+            return None;
+        }
+        Some((offset, non_shared_len as usize))
     }
 
     fn seek_to_restart_point(&mut self, index: u32) {
@@ -126,15 +200,29 @@ impl BlockIter {
             return false;
         }
 
-        // parse entry
+        // parse entry, the lengths must decode and the entry end inside the data region
+        let limit = self.restart_offset as usize;
         let mut offset = self.current as usize;
-        let (shared_len, next): (u32, _) = VarInt::from_varint(&self.data[offset..]).unwrap();
-        offset += next;
-        let (non_shared_len, next): (u32, _) = VarInt::from_varint(&self.data[offset..]).unwrap();
-        offset += next;
-        let (value_len, next): (u32, _) = VarInt::from_varint(&self.data[offset..]).unwrap();
-        offset += next;
-        if offset as u32 + non_shared_len + value_len > self.restart_offset {
+        let mut lens = [0; 3];
+        for len in lens.iter_mut() {
+            match VarInt::from_varint(&self.data[offset..limit]) {
+                Ok((n, next)) => {
+                    *len = n;
+                    offset += next;
+                }
+                Err(_) => {
+                    self.corruption();
+                    return false;
+                }
+            }
+        }
+        let [shared_len, non_shared_len, value_len]: [u32; 3] = lens;
+        if offset as u64 + non_shared_len as u64 + value_len as u64 > limit as u64 {
+            self.corruption();
+            return false;
+        }
+        // the key is cleared at a restart point, so its entry must not share a prefix
+        if shared_len as usize > self.key.len() {
             self.corruption();
             return false;
         }
@@ -160,20 +248,30 @@ impl BlockIter {
     }
 
     fn corruption(&mut self) {
+        self.invalidate();
+        self.status = Some(Corruption::new(CorruptionKind::BadEntry, "bad entry in block").into());
+    }
+
+    // drop the current entry, so nothing points past the data region
+    fn invalidate(&mut self) {
         self.current = self.restart_offset;
         self.restart_index = self.restart_count;
-        self.status = Some(DBError::Corruption("bad entry in block".into()));
+        self.key.clear();
+        self.shared_len = 0;
+        self.non_shared_len = 0;
+        self.key_offset = self.restart_offset;
+        self.value_len = 0;
     }
 
     #[inline]
     fn required_valid(&self) {
-        assert!(self.is_valid());
+        assert!(self.is_valid(), "block iterator is not valid");
     }
 }
 
 impl Iterator for BlockIter {
     fn is_valid(&self) -> bool {
-        self.status.is_none() && self.current < self.restart_count
+        self.status.is_none() && self.current < self.restart_offset
     }
 
     fn next(&mut self) {
@@ -186,10 +284,18 @@ impl Iterator for BlockIter {
         self.required_valid();
 
         let original = self.current;
-        while self.get_restart_point(self.restart_index) >= original {
-            if self.restart_count == 0 {
-                self.current = self.restart_offset;
-                self.restart_index = self.restart_count;
+        loop {
+            let point = self.get_restart_point(self.restart_index);
+            if point < original {
+                break;
+            }
+            if self.restart_index == 0 {
+                // no entry before the first one
+                self.invalidate();
+                return;
+            }
+            if self.get_restart_point(self.restart_index - 1) > point {
+                self.corruption();
                 return;
             }
             self.restart_index -= 1;
@@ -199,6 +305,10 @@ impl Iterator for BlockIter {
         while self.parse_next_entry() && self.next_entry_offset() < original {
             self.current = self.next_entry_offset();
         }
+        // a restart point off an entry boundary can step over the original entry
+        if self.is_valid() && self.current >= original {
+            self.corruption();
+        }
     }
 
     fn key(&self) -> &[u8] {
@@ -214,12 +324,19 @@ impl Iterator for BlockIter {
     }
 
     fn seek_to_first(&mut self) {
+        if self.restart_count == 0 {
+            self.invalidate();
+            return;
+        }
         self.seek_to_restart_point(0);
         self.parse_next_entry();
     }
 
     fn seek_to_last(&mut self) {
-        assert!(self.restart_count > 0);
+        if self.restart_count == 0 {
+            self.invalidate();
+            return;
+        }
         self.seek_to_restart_point(self.restart_count - 1);
         while self.parse_next_entry() && self.next_entry_offset() < self.restart_offset {
             self.current = self.next_entry_offset();
@@ -227,30 +344,25 @@ impl Iterator for BlockIter {
     }
 
     fn seek(&mut self, target: &[u8]) {
+        if self.restart_count == 0 {
+            self.invalidate();
+            return;
+        }
+
         let mut l = 0;
         let mut r = self.restart_count - 1;
 
         while l < r {
             let mid = (l + r + 1) / 2;
-            let region_offset = self.get_restart_point(mid);
-
-            let mut offset = region_offset as usize;
-            let (shared_len, next): (u32, _) = VarInt::from_varint(&self.data[offset..]).unwrap();
-            offset += next;
-            let (non_shared_len, next): (u32, _) =
-                VarInt::from_varint(&self.data[offset..]).unwrap();
-            offset += next;
-            let (_value_len, next): (u32, _) = VarInt::from_varint(&self.data[offset..]).unwrap();
-            offset += next;
-
-            let key_offset = offset;
-            if shared_len != 0 {
-                self.corruption();
-                return;
-            }
-
-            let key_len = (shared_len + non_shared_len) as usize;
-            let mid_key = &self.data[key_offset as usize..key_offset + key_len];
+            let (key_offset, key_len) = match self.restart_key(mid) {
+                Some(key) => key,
+                None => {
+                    self.corruption();
+                    return;
+                }
+            };
+
+            let mid_key = &self.data[key_offset..key_offset + key_len];
             match self.comparator.compare(mid_key, target) {
                 Ordering::Less => l = mid,
                 _ => r = mid - 1,
@@ -276,4 +388,375 @@ impl Iterator for BlockIter {
             None => Ok(()),
         }
     }
+
+    fn reset(&mut self) {
+        self.invalidate();
+        self.status = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::{BufMut, Bytes};
+
+    use crate::{
+        config::ConfigBuilder,
+        error::DBError,
+        iterator::Iterator,
+        table::block_builder::BlockBuilder,
+        utils::{comparators::BytewiseComparator, encoding},
+    };
+
+    use super::{Block, BlockIter};
+
+    fn build_block(count: usize) -> Block {
+        build_block_with(count, ConfigBuilder::default().block_restart_interval(4))
+    }
+
+    fn build_block_with(count: usize, config: &mut ConfigBuilder) -> Block {
+        let mut builder = BlockBuilder::new(config.build());
+        for i in 0..count {
+            let key = format!("key{:08}", i);
+            builder.add(key.as_bytes(), i.to_string().as_bytes());
+        }
+        Block::new(Bytes::copy_from_slice(builder.finish().unwrap())).unwrap()
+    }
+
+    fn make_block(entries: &[u8], restarts: &[u32]) -> Bytes {
+        let mut buf = entries.to_vec();
+        for r in restarts {
+            buf.put_u32_le(*r);
+        }
+        buf.put_u32_le(restarts.len() as u32);
+        Bytes::from(buf)
+    }
+
+    fn check_seek(block: Block, count: usize) {
+        let mut iter = block.iter(std::sync::Arc::new(BytewiseComparator));
+
+        iter.seek_to_first();
+        for i in 0..count {
+            assert!(iter.is_valid());
+            assert_eq!(iter.key(), format!("key{:08}", i).as_bytes());
+            assert_eq!(iter.value(), i.to_string().as_bytes());
+            iter.next();
+        }
+        assert!(!iter.is_valid());
+
+        for i in (0..count).rev() {
+            iter.seek(format!("key{:08}", i).as_bytes());
+            assert_eq!(iter.value(), i.to_string().as_bytes());
+        }
+        iter.seek(b"key");
+        assert_eq!(iter.value(), b"0");
+        iter.seek(b"kez");
+        assert!(!iter.is_valid());
+    }
+
+    #[test]
+    fn empty_block() {
+        assert!(Block::new(make_block(&[], &[0])).is_ok());
+    }
+
+    #[test]
+    fn seek_in_empty_block() {
+        let mut builder = BlockBuilder::new(ConfigBuilder::default().build());
+        let built = Block::new(Bytes::copy_from_slice(builder.finish().unwrap())).unwrap();
+        // a block without restart points is never built, but still decodes
+        let no_restarts = Block::new(make_block(&[], &[])).unwrap();
+
+        for block in [built, no_restarts] {
+            let mut iter = block.iter(std::sync::Arc::new(BytewiseComparator));
+            iter.seek_to_first();
+            assert!(!iter.is_valid());
+            iter.seek_to_last();
+            assert!(!iter.is_valid());
+            iter.seek(b"");
+            assert!(!iter.is_valid());
+            assert!(iter.status().is_ok());
+        }
+    }
+
+    #[test]
+    fn first_and_last_key() {
+        let comp = std::sync::Arc::new(BytewiseComparator);
+        for count in [1, 4, 5, 100] {
+            let block = build_block(count);
+            let first = block.first_key(comp.clone()).unwrap();
+            let last = block.last_key(comp.clone()).unwrap();
+            assert_eq!(first, b"key00000000".as_slice());
+            assert_eq!(last, format!("key{:08}", count - 1).as_bytes());
+        }
+
+        let empty = Block::new(make_block(&[], &[0])).unwrap();
+        assert_eq!(empty.first_key(comp.clone()), None);
+        assert_eq!(empty.last_key(comp), None);
+    }
+
+    #[test]
+    fn bad_restart_points() {
+        // | shared 0 | non-shared 1 | value 1 | "a" | "a" |
+        let entry = [0, 1, 1, b'a', b'a'];
+        assert!(Block::new(make_block(&entry, &[0])).is_ok());
+
+        let cases: [&[u32]; 4] = [&[5], &[0, 1000], &[u32::MAX], &[3, 0]];
+        for restarts in cases {
+            let res = Block::new(make_block(&entry, restarts));
+            assert!(
+                matches!(res, Err(DBError::Corruption(_))),
+                "restarts: {:?}",
+                restarts
+            );
+        }
+    }
+
+    #[test]
+    fn restart_point_with_shared_prefix() {
+        // | 0 | 1 | 1 | "a" | "a" |, then | 1 | 1 | 1 | "b" | "b" | for key "ab" at a restart point
+        let entries = [0, 1, 1, b'a', b'a', 1, 1, 1, b'b', b'b'];
+        assert!(Block::new(make_block(&entries, &[0])).is_ok());
+        let res = Block::new(make_block(&entries, &[0, 5]));
+        assert!(matches!(res, Err(DBError::Corruption(_))));
+    }
+
+    #[test]
+    fn any_restart_interval() {
+        const COUNT: usize = 1000;
+        let comp = std::sync::Arc::new(BytewiseComparator);
+        let mut config = ConfigBuilder::default();
+        for interval in [1, 2, 16, 255] {
+            config.block_restart_interval(interval);
+            let block = build_block_with(COUNT, &mut config);
+            check_seek(build_block_with(COUNT, &mut config), COUNT);
+
+            let mut iter = block.iter(comp.clone());
+            iter.seek_to_last();
+            for i in (0..COUNT).rev() {
+                assert_eq!(
+                    iter.value(),
+                    i.to_string().as_bytes(),
+                    "interval {}",
+                    interval
+                );
+                iter.prev();
+            }
+            assert!(!iter.is_valid());
+
+            // step both ways from every seek target
+            for i in (1..COUNT - 1).step_by(7) {
+                iter.seek(format!("key{:08}", i).as_bytes());
+                iter.prev();
+                assert_eq!(iter.value(), (i - 1).to_string().as_bytes());
+                iter.next();
+                iter.next();
+                assert_eq!(iter.value(), (i + 1).to_string().as_bytes());
+            }
+            assert!(iter.status().is_ok());
+        }
+    }
+
+    #[test]
+    fn seek_entries() {
+        check_seek(build_block(1000), 1000);
+    }
+
+    #[test]
+    fn seek_restart_every_entry() {
+        let mut config = ConfigBuilder::default();
+        config.block_restart_interval(1);
+        check_seek(build_block_with(1000, &mut config), 1000);
+    }
+
+    #[test]
+    fn prev_entries() {
+        let block = build_block(1000);
+        let mut iter = block.iter(std::sync::Arc::new(BytewiseComparator));
+        iter.seek_to_last();
+        for i in (0..1000).rev() {
+            assert_eq!(iter.key(), format!("key{:08}", i).as_bytes());
+            iter.prev();
+        }
+        assert!(!iter.is_valid());
+        assert!(iter.status().is_ok());
+    }
+
+    #[test]
+    fn prev_with_unordered_restarts() {
+        // three entries at 0, 5 and 10, the restart array bypasses Block::new validation
+        let entries = [
+            0, 1, 1, b'a', b'a', 0, 1, 1, b'b', b'b', 0, 1, 1, b'c', b'c',
+        ];
+        let data = make_block(&entries, &[0, 10, 5]);
+        let restarts: std::sync::Arc<[u32]> = std::sync::Arc::new([0, 10, 5]);
+        let comp = std::sync::Arc::new(BytewiseComparator);
+        let mut iter = BlockIter::new(data, entries.len() as u32, restarts, comp);
+
+        iter.seek_to_last();
+        assert_eq!(iter.key(), b"c");
+        iter.prev();
+        assert_eq!(iter.key(), b"b");
+        iter.prev();
+        assert!(!iter.is_valid());
+        assert!(matches!(iter.status(), Err(DBError::Corruption(_))));
+    }
+
+    #[test]
+    fn seek_past_last_key() {
+        let mut config = ConfigBuilder::default();
+        for interval in [1, 4, 16] {
+            config.block_restart_interval(interval);
+            let block = build_block_with(1000, &mut config);
+            let mut iter = block.iter(std::sync::Arc::new(BytewiseComparator));
+
+            // past the last key of the last restart region
+            iter.seek(b"key00000999a");
+            assert!(!iter.is_valid());
+            assert!(iter.status().is_ok());
+
+            iter.seek(b"key00000999");
+            assert_eq!(iter.value(), b"999");
+            iter.next();
+            assert!(!iter.is_valid());
+            assert!(iter.status().is_ok());
+        }
+
+        // no restart points at all
+        let block = Block::new(make_block(&[], &[])).unwrap();
+        let mut iter = block.iter(std::sync::Arc::new(BytewiseComparator));
+        iter.seek(b"a");
+        assert!(!iter.is_valid());
+        assert!(iter.status().is_ok());
+    }
+
+    #[test]
+    fn seek_corrupted_restart_key() {
+        // the second restart entry claims a key longer than the data region
+        let entries = [0, 1, 1, b'a', b'a', 0, 100, 1, b'b', b'b'];
+        let block = Block::new(make_block(&entries, &[0, 5])).unwrap();
+        let mut iter = block.iter(std::sync::Arc::new(BytewiseComparator));
+        iter.seek(b"b");
+        assert!(!iter.is_valid());
+        assert!(matches!(iter.status(), Err(DBError::Corruption(_))));
+    }
+
+    #[test]
+    fn corrupted_entry() {
+        // | shared 0 | non-shared 1 | value 200 | "a" |, the value is past the data region
+        let block = Block::new(make_block(&[0, 1, 200, b'a'], &[0])).unwrap();
+        let mut iter = block.iter(std::sync::Arc::new(BytewiseComparator));
+        iter.seek_to_first();
+        assert!(!iter.is_valid());
+        assert!(iter.key.is_empty());
+        assert_eq!(iter.next_entry_offset(), iter.restart_offset);
+
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            iter.value();
+        }));
+        assert!(res.is_err());
+        assert!(matches!(iter.status(), Err(DBError::Corruption(_))));
+        assert!(!iter.is_valid());
+    }
+
+    #[test]
+    fn truncated_entry_lengths() {
+        // the value length varint runs into the restart array
+        let block = Block::new(make_block(&[0, 1, 0x80], &[0])).unwrap();
+        let mut iter = block.iter(std::sync::Arc::new(BytewiseComparator));
+        iter.seek_to_first();
+        assert!(!iter.is_valid());
+        assert!(matches!(iter.status(), Err(DBError::Corruption(_))));
+    }
+
+    #[test]
+    fn entry_lengths_overflow() {
+        // non-shared and value lengths of u32::MAX, whose sum overflows a u32
+        let max = [0xff, 0xff, 0xff, 0xff, 0x0f];
+        for entry in [
+            [&[0, 1][..], &max, b"a"].concat(),
+            [&[0][..], &max, &max, b"a"].concat(),
+        ] {
+            let block = Block::new(make_block(&entry, &[0])).unwrap();
+            let mut iter = block.iter(std::sync::Arc::new(BytewiseComparator));
+            iter.seek_to_first();
+            assert!(!iter.is_valid());
+            assert!(matches!(iter.status(), Err(DBError::Corruption(_))));
+        }
+    }
+
+    #[test]
+    fn empty_keys_and_values() {
+        let config = ConfigBuilder::default().block_restart_interval(2).build();
+        let mut builder = BlockBuilder::new(config);
+        let entries: [(&[u8], &[u8]); 4] = [(b"", b""), (b"", b"v"), (b"a", b""), (b"b", b"")];
+        for (key, value) in entries {
+            builder.add(key, value);
+        }
+        let block = Block::new(Bytes::copy_from_slice(builder.finish().unwrap())).unwrap();
+
+        let mut iter = block.iter(std::sync::Arc::new(BytewiseComparator));
+        iter.seek_to_first();
+        for (key, value) in entries {
+            assert_eq!(iter.key(), key);
+            assert_eq!(iter.value(), value);
+            iter.next();
+        }
+        assert!(!iter.is_valid());
+
+        iter.seek(b"");
+        assert_eq!(iter.value(), b"");
+        iter.seek(b"b");
+        assert_eq!(iter.key(), b"b");
+        assert_eq!(iter.value(), b"");
+    }
+
+    #[test]
+    fn reset_iterator() {
+        let block = build_block(100);
+        let mut iter = block.iter(std::sync::Arc::new(BytewiseComparator));
+        for i in (0..100).step_by(7) {
+            iter.reset();
+            assert!(!iter.is_valid());
+            assert!(iter.key.is_empty());
+            iter.seek(format!("key{:08}", i).as_bytes());
+            assert_eq!(iter.key(), format!("key{:08}", i).as_bytes());
+        }
+
+        let block = Block::new(make_block(&[0, 1, 200, b'a'], &[0])).unwrap();
+        let mut iter = block.iter(std::sync::Arc::new(BytewiseComparator));
+        iter.seek_to_first();
+        iter.reset();
+        assert!(iter.status().is_ok());
+        assert!(!iter.is_valid());
+    }
+
+    #[test]
+    fn cached_restarts_match_block() {
+        const COUNT: usize = 1000;
+        let block = build_block(COUNT);
+
+        let trailer = &block.data[block.restart_offset as usize..block.data.len() - 4];
+        let raw: Vec<u32> = trailer.chunks_exact(4).map(encoding::get_u32).collect();
+        assert_eq!(&*block.restarts, raw.as_slice());
+
+        let keys: Vec<_> = (0..COUNT).map(|i| format!("key{:08}", i)).collect();
+        let mut iter = block.iter(std::sync::Arc::new(BytewiseComparator));
+        for (i, key) in keys.iter().enumerate() {
+            iter.seek(key.as_bytes());
+            assert_eq!(iter.key(), key.as_bytes());
+
+            // a key between two entries lands on the next one
+            iter.seek(format!("{}a", key).as_bytes());
+            match keys.get(i + 1) {
+                Some(next) => assert_eq!(iter.key(), next.as_bytes()),
+                None => assert!(!iter.is_valid()),
+            }
+        }
+
+        iter.seek_to_last();
+        for key in keys.iter().rev() {
+            assert_eq!(iter.key(), key.as_bytes());
+            iter.prev();
+        }
+        assert!(!iter.is_valid());
+    }
 }
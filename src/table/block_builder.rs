@@ -2,10 +2,27 @@ use std::sync::Arc;
 
 use bytes::BufMut;
 
-use crate::{config::Config, utils::varint::VarInt};
+use crate::{
+    config::Config,
+    error::{DBError, DBResult},
+    utils::{encoding, varint::VarInt},
+};
 
 use super::SIZE_U32;
 
+// restart points are u32 offsets into the block
+pub(crate) const MAX_BLOCK_SIZE: usize = u32::MAX as usize;
+
+// | entries | restarts | restart count |
+fn finished_block_len(data_len: usize, restart_count: usize) -> DBResult<usize> {
+    let len = restart_count
+        .checked_add(1)
+        .and_then(|n| n.checked_mul(SIZE_U32))
+        .and_then(|n| n.checked_add(data_len))
+        .filter(|&n| n <= MAX_BLOCK_SIZE);
+    len.ok_or(DBError::BlockTooLarge(data_len))
+}
+
 pub struct BlockBuilder {
     buf: Vec<u8>,
     restarts: Vec<u32>,
@@ -85,18 +102,22 @@ impl BlockBuilder {
         self.counter += 1;
     }
 
-    pub fn finish(&mut self) -> &[u8] {
+    pub fn finish(&mut self) -> DBResult<&[u8]> {
         assert!(!self.finished);
+        finished_block_len(self.buf.len(), self.restarts.len())?;
         for offset in self.restarts.iter() {
-            self.buf.put_u32_le(*offset);
+            encoding::put_u32(&mut self.buf, *offset);
         }
-        self.buf.put_u32_le(self.restarts.len() as u32);
+        encoding::put_u32(&mut self.buf, self.restarts.len() as u32);
         self.finished = true;
-        &self.buf
+        Ok(&self.buf)
     }
 
     pub fn current_size_estimate(&self) -> usize {
-        self.buf.len() + self.restarts.len() * SIZE_U32 + SIZE_U32
+        self.buf
+            .len()
+            .saturating_add(self.restarts.len().saturating_mul(SIZE_U32))
+            .saturating_add(SIZE_U32)
     }
 
     pub fn reset(&mut self) {
@@ -118,9 +139,9 @@ impl BlockBuilder {
 mod tests {
     use std::{sync::Arc, vec};
 
-    use crate::{comparator::Comparator, config::ConfigBuilder};
+    use crate::{comparator::Comparator, config::ConfigBuilder, error::DBError};
 
-    use super::BlockBuilder;
+    use super::{finished_block_len, BlockBuilder, MAX_BLOCK_SIZE};
 
     struct TestComparator;
 
@@ -155,8 +176,58 @@ mod tests {
         for input in inputs.iter() {
             builder.add(input.as_bytes(), input.as_bytes());
         }
-        builder.finish();
+        builder.finish().unwrap();
 
         assert_eq!(builder.restarts, vec![0, 18, 44]);
     }
+
+    #[test]
+    fn restart_array_is_little_endian() {
+        let config = ConfigBuilder::default()
+            .block_restart_interval(1)
+            .comparator(Arc::new(TestComparator))
+            .build();
+        let mut builder = BlockBuilder::new(config);
+        builder.add(b"a", b"a");
+        builder.add(b"b", b"b");
+        let block = builder.finish().unwrap();
+
+        // | entries 5b * 2 | restart 0 | restart 5 | restart count 2 |
+        assert_eq!(block[10..], [0, 0, 0, 0, 5, 0, 0, 0, 2, 0, 0, 0]);
+    }
+
+    #[test]
+    fn restart_every_entry() {
+        let inputs = ["a", "ab", "abc", "acd"];
+
+        let config = ConfigBuilder::default()
+            .block_restart_interval(1)
+            .comparator(Arc::new(TestComparator))
+            .build();
+        let mut builder = BlockBuilder::new(config);
+
+        for input in inputs.iter() {
+            builder.add(input.as_bytes(), input.as_bytes());
+        }
+        builder.finish().unwrap();
+
+        assert_eq!(builder.restarts, vec![0, 5, 12, 21]);
+    }
+
+    #[test]
+    fn block_size_limit() {
+        // the largest block still fits u32 restart offsets
+        assert_eq!(
+            finished_block_len(MAX_BLOCK_SIZE - 8, 1).unwrap(),
+            MAX_BLOCK_SIZE
+        );
+        assert!(matches!(
+            finished_block_len(MAX_BLOCK_SIZE - 7, 1),
+            Err(DBError::BlockTooLarge(_))
+        ));
+        assert!(matches!(
+            finished_block_len(0, usize::MAX),
+            Err(DBError::BlockTooLarge(_))
+        ));
+    }
 }
@@ -1,11 +1,17 @@
 use bytes::BufMut;
 
-use crate::utils::varint::VarInt;
+use crate::{
+    error::{Corruption, CorruptionKind, DBResult},
+    utils::{encoding, varint::VarInt},
+};
 
 pub(crate) const MAX_ENCODE_LEN: usize = 10 + 10;
 
 pub(crate) const MAGIT_NUMBER: u64 = 1145141919810;
 
+// | meta index handle | index handle | padding | magic number 8b |
+pub(crate) const FOOTER_ENCODE_LEN: usize = MAX_ENCODE_LEN * 2 + std::mem::size_of::<u64>();
+
 #[derive(Debug, Clone, Copy)]
 pub struct BlockHandle {
     offset: u64,
@@ -35,6 +41,22 @@ impl BlockHandle {
         self.encode_to(&mut buf);
         buf
     }
+
+    /// Decode a handle from the front of `buf`, return it with the number of bytes read.
+    pub fn decode_from(buf: &[u8]) -> DBResult<(Self, usize)> {
+        let bad_handle = |_| Corruption::new(CorruptionKind::BadOffset, "bad block handle");
+        let (offset, l1) = u64::from_varint(buf).map_err(bad_handle)?;
+        let (size, l2) = u64::from_varint(&buf[l1..]).map_err(bad_handle)?;
+        Ok((Self::new(offset, size), l1 + l2))
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
 }
 
 pub struct Footer {
@@ -51,11 +73,117 @@ impl Footer {
     }
 
     pub fn encode(&self) -> Vec<u8> {
-        let mut buf = Vec::with_capacity(MAX_ENCODE_LEN * 2 + std::mem::size_of::<u64>());
+        let mut buf = Vec::with_capacity(FOOTER_ENCODE_LEN);
         self.meta_index_handle.encode_to(&mut buf);
         self.index_handle.encode_to(&mut buf);
-        buf.put_u64_le(MAGIT_NUMBER);
-        assert_eq!(buf.len(), 48);
+        buf.resize(MAX_ENCODE_LEN * 2, 0);
+        encoding::put_u64(&mut buf, MAGIT_NUMBER);
+        assert_eq!(buf.len(), FOOTER_ENCODE_LEN);
         buf
     }
+
+    /// Decode the footer from the last [`FOOTER_ENCODE_LEN`] bytes of a table.
+    pub fn decode(buf: &[u8]) -> DBResult<Self> {
+        if buf.len() < FOOTER_ENCODE_LEN {
+            return Err(Corruption::new(
+                CorruptionKind::Truncated,
+                format!(
+                    "truncated footer: {} bytes, expected {}",
+                    buf.len(),
+                    FOOTER_ENCODE_LEN
+                ),
+            )
+            .into());
+        }
+
+        let buf = &buf[buf.len() - FOOTER_ENCODE_LEN..];
+        let magic = encoding::get_u64(&buf[MAX_ENCODE_LEN * 2..]);
+        if magic != MAGIT_NUMBER {
+            return Err(Corruption::new(
+                CorruptionKind::BadMagic,
+                format!(
+                    "not an aris SSTable: bad magic number, expected {:#x}, found {:#x}",
+                    MAGIT_NUMBER, magic
+                ),
+            )
+            .into());
+        }
+
+        let handles = &buf[..MAX_ENCODE_LEN * 2];
+        let (meta_index_handle, len) = BlockHandle::decode_from(handles)?;
+        let (index_handle, _) = BlockHandle::decode_from(&handles[len..])?;
+        Ok(Self::new(meta_index_handle, index_handle))
+    }
+
+    pub fn meta_index_handle(&self) -> BlockHandle {
+        self.meta_index_handle
+    }
+
+    pub fn index_handle(&self) -> BlockHandle {
+        self.index_handle
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{Corruption, CorruptionKind, DBError};
+
+    use super::{BlockHandle, Footer, FOOTER_ENCODE_LEN, MAGIT_NUMBER};
+
+    fn corruption(buf: &[u8]) -> Corruption {
+        match Footer::decode(buf) {
+            Err(DBError::Corruption(e)) => e,
+            _ => panic!("expected corruption"),
+        }
+    }
+
+    #[test]
+    fn decode_footer() {
+        let footer = Footer::new(BlockHandle::new(1, 2), BlockHandle::new(u64::MAX, 1 << 40));
+        let mut buf = b"table content".to_vec();
+        buf.extend(footer.encode());
+
+        let res = Footer::decode(&buf).unwrap();
+        assert_eq!(res.meta_index_handle().offset(), 1);
+        assert_eq!(res.meta_index_handle().size(), 2);
+        assert_eq!(res.index_handle().offset(), u64::MAX);
+        assert_eq!(res.index_handle().size(), 1 << 40);
+    }
+
+    #[test]
+    fn magic_number_is_little_endian() {
+        let footer = Footer::new(BlockHandle::new(1, 2), BlockHandle::new(3, 4)).encode();
+        assert_eq!(footer[FOOTER_ENCODE_LEN - 8..], MAGIT_NUMBER.to_le_bytes());
+    }
+
+    #[test]
+    fn truncated_footer() {
+        let footer = Footer::new(BlockHandle::new(1, 2), BlockHandle::new(3, 4)).encode();
+        let c = corruption(&footer[1..]);
+        assert_eq!(c.kind, CorruptionKind::Truncated);
+        let msg = c.to_string();
+        assert!(msg.starts_with("truncated footer"), "{}", msg);
+    }
+
+    #[test]
+    fn bad_magic_number() {
+        let buf: Vec<u8> = (0..FOOTER_ENCODE_LEN).map(|_| rand::random()).collect();
+        let c = corruption(&buf);
+        assert_eq!(c.kind, CorruptionKind::BadMagic);
+        let msg = c.to_string();
+        assert!(msg.starts_with("not an aris SSTable"), "{}", msg);
+        assert!(msg.contains("expected 0x"), "{}", msg);
+    }
+
+    #[test]
+    fn bad_block_handle() {
+        let res = BlockHandle::decode_from(&[0xff; 4]);
+        assert!(matches!(
+            res,
+            Err(DBError::Corruption(Corruption {
+                kind: CorruptionKind::BadOffset,
+                ..
+            }))
+        ));
+    }
 }
@@ -1,8 +1,12 @@
 use std::sync::Arc;
 
-use bytes::{Buf, BufMut};
+use bytes::BufMut;
 
-use crate::filter::FilterPolicy;
+use crate::{
+    error::{Corruption, CorruptionKind, DBResult},
+    filter::FilterPolicy,
+    utils::encoding,
+};
 
 const FILTER_BASE_LG: usize = 11;
 const FILTER_BASE: usize = 1 << FILTER_BASE_LG; // 2 kb
@@ -31,13 +35,27 @@ impl FilterBlockBuilder {
         self.keys.push(key);
     }
 
-    pub fn start_block(&mut self, block_offset: u64) {
+    pub fn start_block(&mut self, block_offset: u64) -> DBResult<()> {
         // 每一个 filter 分配 2kb
         let filter_index = block_offset / FILTER_BASE as u64;
-        assert!(filter_index >= self.filter_offsets.len() as u64);
+        if filter_index < self.filter_offsets.len() as u64 {
+            return Err(Corruption::new(
+                CorruptionKind::BadOffset,
+                format!(
+                    "block offset {} goes back to filter {}, {} filters generated",
+                    block_offset,
+                    filter_index,
+                    self.filter_offsets.len()
+                ),
+            )
+            .at(block_offset)
+            .into());
+        }
+        // every round generates one filter, so it stops at filter_index
         while filter_index > self.filter_offsets.len() as u64 {
             self.generate_filter();
         }
+        Ok(())
     }
 
     pub fn generate_filter(&mut self) {
@@ -58,9 +76,9 @@ impl FilterBlockBuilder {
 
         let filter_offset_start = self.buf.len() as u32;
         for &off in self.filter_offsets.iter() {
-            self.buf.put_u32_le(off);
+            encoding::put_u32(&mut self.buf, off);
         }
-        self.buf.put_u32_le(filter_offset_start);
+        encoding::put_u32(&mut self.buf, filter_offset_start);
         self.buf.put_u8(FILTER_BASE_LG as u8);
 
         // | filters | filter offsets | filter offset start | base lg |
@@ -91,7 +109,7 @@ impl FilterBlockReader {
             return this;
         }
         this.base_lg = this.data.last().copied().unwrap() as usize;
-        let start_offset = (&this.data[this.data.len() - 5..]).get_u32_le();
+        let start_offset = encoding::get_u32(&this.data[this.data.len() - 5..]);
         if start_offset + 5 > this.data.len() as u32 {
             // should panic?
             return this;
@@ -107,9 +125,10 @@ impl FilterBlockReader {
             return true;
         }
 
-        let start = (&self.data[self.filter_offset + index as usize * 4..]).get_u32_le() as usize;
+        let start =
+            encoding::get_u32(&self.data[self.filter_offset + index as usize * 4..]) as usize;
         let limit =
-            (&self.data[self.filter_offset + (index + 1) as usize * 4..]).get_u32_le() as usize;
+            encoding::get_u32(&self.data[self.filter_offset + (index + 1) as usize * 4..]) as usize;
 
         if start <= limit && limit <= self.filter_offset {
             return self.policy.may_contain(&self.data[start..limit], key);
@@ -128,6 +147,7 @@ mod tests {
     use bytes::{Buf, BufMut};
 
     use crate::{
+        error::DBError,
         filter::FilterPolicy,
         table::filter_block::{FilterBlockReader, FILTER_BASE_LG},
         utils::hash::basic_hash,
@@ -177,13 +197,13 @@ mod tests {
     #[test]
     fn single_chunk() {
         let mut builder = FilterBlockBuilder::new(Arc::new(TestHashFilter));
-        builder.start_block(100);
+        builder.start_block(100).unwrap();
         builder.add_key(b"foo");
         builder.add_key(b"bar");
         builder.add_key(b"box");
-        builder.start_block(200);
+        builder.start_block(200).unwrap();
         builder.add_key(b"box");
-        builder.start_block(300);
+        builder.start_block(300).unwrap();
         builder.add_key(b"hello");
         let block = builder.finish();
 
@@ -201,19 +221,19 @@ mod tests {
         let mut builder = FilterBlockBuilder::new(Arc::new(TestHashFilter));
 
         // First filter
-        builder.start_block(0);
+        builder.start_block(0).unwrap();
         builder.add_key("foo".as_bytes());
-        builder.start_block(2000);
+        builder.start_block(2000).unwrap();
         builder.add_key("bar".as_bytes());
 
         // Second filter
-        builder.start_block(3100);
+        builder.start_block(3100).unwrap();
         builder.add_key("box".as_bytes());
 
         // Third filter is empty
 
         // Last filter
-        builder.start_block(9000);
+        builder.start_block(9000).unwrap();
         builder.add_key("box".as_bytes());
         builder.add_key("hello".as_bytes());
 
@@ -244,4 +264,28 @@ mod tests {
         assert!(!reader.key_may_match(9000, "bar".as_bytes()));
         assert!(reader.key_may_match(9000, "hello".as_bytes()));
     }
+
+    #[test]
+    fn block_offset_goes_back() {
+        let mut builder = FilterBlockBuilder::new(Arc::new(TestHashFilter));
+        builder.start_block(0).unwrap();
+        builder.add_key(b"foo");
+        builder.start_block(5000).unwrap();
+        builder.add_key(b"bar");
+        builder.start_block(4096).unwrap();
+
+        assert!(matches!(
+            builder.start_block(100),
+            Err(DBError::Corruption(_))
+        ));
+
+        // the builder is still usable
+        builder.start_block(9000).unwrap();
+        builder.add_key(b"box");
+        let block = builder.finish();
+        let reader = FilterBlockReader::new(Arc::new(TestHashFilter), block.to_vec());
+        assert!(reader.key_may_match(0, b"foo"));
+        assert!(reader.key_may_match(4096, b"bar"));
+        assert!(reader.key_may_match(9000, b"box"));
+    }
 }
@@ -2,6 +2,9 @@ pub mod block_builder;
 pub mod filter_block;
 pub mod block;
 pub mod table_builder;
+pub mod table_reader;
+pub mod sst_file_writer;
+pub mod two_level_iterator;
 pub mod block_handler;
 
 const SIZE_U32: usize = std::mem::size_of::<u32>();
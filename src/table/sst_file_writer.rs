@@ -0,0 +1,257 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    comparator::Comparator,
+    config::Config,
+    error::{DBError, DBResult},
+    io::default_backend,
+    utils::comparators::{pack_tail, KeyTailEncoding, ValueType, MAX_SEQUENCE},
+};
+
+use super::table_builder::TableBuilder;
+
+/// A finished table file, what a manifest records of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMeta {
+    pub path: PathBuf,
+    pub file_size: u64,
+    pub entries_count: u64,
+    /// The smallest and largest internal keys of the table.
+    pub smallest: Vec<u8>,
+    pub largest: Vec<u8>,
+    /// The seq every entry of the table was written with.
+    pub sequence: u64,
+}
+
+/// Builds a table from user keys in ascending order without a running db, e.g.
+/// to bulk load the output of an external sort. Every entry gets the same seq,
+/// so a user key is added at most once. The table holds internal keys, open it
+/// with [`Config::with_internal_keys`] of the config it was written with.
+pub struct SstFileWriter {
+    path: PathBuf,
+    user_comparator: Arc<dyn Comparator>,
+    sequence: u64,
+    builder: TableBuilder,
+    last_user_key: Vec<u8>,
+}
+
+impl SstFileWriter {
+    pub fn new(path: impl AsRef<Path>, config: Arc<Config>) -> DBResult<Self> {
+        Self::with_sequence(path, config, 0)
+    }
+
+    /// Create a writer that tags every entry with `sequence`.
+    pub fn with_sequence(
+        path: impl AsRef<Path>,
+        config: Arc<Config>,
+        sequence: u64,
+    ) -> DBResult<Self> {
+        if sequence > MAX_SEQUENCE {
+            return Err(DBError::InvalidArgument(format!(
+                "sequence {} too large",
+                sequence
+            )));
+        }
+
+        let path = path.as_ref().to_path_buf();
+        let fd = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .read(true)
+            .write(true)
+            .open(&path)?;
+        let builder =
+            TableBuilder::new(config.with_internal_keys(), Arc::new(fd), default_backend());
+
+        Ok(Self {
+            path,
+            user_comparator: config.comparator.clone(),
+            sequence,
+            builder,
+            last_user_key: Vec::new(),
+        })
+    }
+
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> DBResult<()> {
+        self.add(key, value, ValueType::Value)
+    }
+
+    pub fn delete(&mut self, key: &[u8]) -> DBResult<()> {
+        self.add(key, &[], ValueType::Deletion)
+    }
+
+    fn add(&mut self, key: &[u8], value: &[u8], ty: ValueType) -> DBResult<()> {
+        if self.builder.entries_count() > 0
+            && self
+                .user_comparator
+                .compare(key, &self.last_user_key)
+                .is_le()
+        {
+            return Err(DBError::InvalidArgument(format!(
+                "keys must be strictly ascending: {:?} after {:?}",
+                key, self.last_user_key
+            )));
+        }
+
+        let mut internal_key = Vec::with_capacity(key.len() + 8);
+        internal_key.extend_from_slice(key);
+        KeyTailEncoding::Fixed.put_tail(&mut internal_key, pack_tail(self.sequence, ty));
+        self.builder.add(&internal_key, value)?;
+
+        self.last_user_key.clear();
+        self.last_user_key.extend_from_slice(key);
+        Ok(())
+    }
+
+    /// Write the index and footer and sync the file. A table without entries
+    /// is an error, it has no key range to be placed by.
+    pub fn finish(mut self) -> DBResult<FileMeta> {
+        if self.builder.entries_count() == 0 {
+            self.builder.abandon();
+            return Err(DBError::InvalidArgument(format!(
+                "no entries written to {}",
+                self.path.display()
+            )));
+        }
+
+        self.builder.finish()?;
+        Ok(FileMeta {
+            path: self.path,
+            file_size: self.builder.file_size(),
+            entries_count: self.builder.entries_count(),
+            smallest: self.builder.smallest_key().to_vec(),
+            largest: self.builder.largest_key().to_vec(),
+            sequence: self.sequence,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        config::ConfigBuilder,
+        error::DBError,
+        filter::BloomFilter,
+        io::default_backend,
+        iterator::Iterator,
+        table::{block_handler::BlockHandle, table_reader::Table},
+        utils::{
+            comparators::{
+                pack_tail, BytewiseComparator, InternalKeyComparator, KeyTailEncoding, ValueType,
+                MAX_SEQUENCE,
+            },
+            testing::TempFile,
+        },
+    };
+
+    use super::SstFileWriter;
+
+    #[test]
+    fn write_and_read_back() {
+        let file = TempFile::new("sst_file_writer");
+        let path = file.path();
+        let config = ConfigBuilder::default()
+            .block_size(256)
+            .filter_policy(Arc::new(BloomFilter::new(10)))
+            .build();
+        let mut writer = SstFileWriter::with_sequence(path, config.clone(), 42).unwrap();
+        for i in 0..500 {
+            let key = format!("key{:06}", i);
+            if i % 10 == 0 {
+                writer.delete(key.as_bytes()).unwrap();
+            } else {
+                writer.put(key.as_bytes(), key.as_bytes()).unwrap();
+            }
+        }
+        let meta = writer.finish().unwrap();
+        assert_eq!(meta.entries_count, 500);
+        assert_eq!(meta.sequence, 42);
+        assert_eq!(meta.file_size, std::fs::metadata(path).unwrap().len());
+
+        let fd = Arc::new(std::fs::File::open(path).unwrap());
+        let table = Table::open(
+            config.with_internal_keys(),
+            fd,
+            default_backend(),
+            meta.file_size,
+        )
+        .unwrap();
+        let comp = InternalKeyComparator::new(BytewiseComparator);
+        let mut iter = table.iter();
+        iter.seek_to_first();
+        let mut i = 0;
+        while iter.is_valid() {
+            let (user_key, seq, ty) = comp.parse(iter.key()).unwrap();
+            let key = format!("key{:06}", i);
+            assert_eq!(user_key, key.as_bytes());
+            assert_eq!(seq, 42);
+            if i % 10 == 0 {
+                assert_eq!(ty, ValueType::Deletion);
+                assert!(iter.value().is_empty());
+            } else {
+                assert_eq!(ty, ValueType::Value);
+                assert_eq!(iter.value(), key.as_bytes());
+            }
+            if i == 0 {
+                assert_eq!(iter.key(), meta.smallest);
+            }
+            if i == 499 {
+                assert_eq!(iter.key(), meta.largest);
+            }
+            i += 1;
+            iter.next();
+        }
+        assert!(iter.status().is_ok());
+        assert_eq!(i, 500);
+
+        // the filter holds user keys, a lookup at any seq matches
+        assert!(table.filter().is_some());
+        let mut index = table.index_block().iter(table.comparator().clone());
+        index.seek_to_first();
+        let mut i = 0;
+        while index.is_valid() {
+            let (handle, _) = BlockHandle::decode_from(index.value()).unwrap();
+            let block = table.read_data_block(handle).unwrap();
+            let mut iter = block.iter(table.comparator().clone());
+            iter.seek_to_first();
+            while iter.is_valid() {
+                let (user_key, _, _) = comp.parse(iter.key()).unwrap();
+                for seq in [0, 42, MAX_SEQUENCE] {
+                    let mut key = user_key.to_vec();
+                    KeyTailEncoding::Fixed.put_tail(&mut key, pack_tail(seq, ValueType::Value));
+                    assert!(table.key_may_match(handle.offset(), &key));
+                }
+                i += 1;
+                iter.next();
+            }
+            index.next();
+        }
+        assert_eq!(i, 500);
+    }
+
+    #[test]
+    fn unsorted_keys() {
+        let file = TempFile::new("sst_file_writer_unsorted");
+        let path = file.path();
+        let mut writer = SstFileWriter::new(path, ConfigBuilder::default().build()).unwrap();
+        writer.put(b"b", b"1").unwrap();
+        assert!(matches!(
+            writer.put(b"a", b"2"),
+            Err(DBError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            writer.delete(b"b"),
+            Err(DBError::InvalidArgument(_))
+        ));
+        writer.put(b"c", b"3").unwrap();
+        assert_eq!(writer.finish().unwrap().entries_count, 2);
+
+        let writer = SstFileWriter::new(path, ConfigBuilder::default().build()).unwrap();
+        assert!(matches!(writer.finish(), Err(DBError::InvalidArgument(_))));
+    }
+}
@@ -1,11 +1,11 @@
 use std::{sync::Arc, vec};
 
-use bytes::BufMut;
-
 use crate::{
-    config::{CompressionType, Config},
-    error::DBResult,
+    config::{CompressionType, Config, DuplicateKeyPolicy, TableOptions},
+    error::{DBError, DBResult},
+    io::IoBackend,
     table::block_handler::Footer,
+    utils::{encoding, fs::preallocate},
 };
 
 use super::{
@@ -20,13 +20,15 @@ pub struct TableBuilder {
 
     // write file
     fd: Arc<std::fs::File>,
-    ring: rio::Rio,
+    io: Arc<dyn IoBackend>,
     append_offset: u64,
 
     data_block: BlockBuilder,
     index_block: BlockBuilder,
+    first_key: Vec<u8>,
     last_key: Vec<u8>,
     entries_count: u64,
+    data_blocks_count: u64,
 
     closed: bool,
 
@@ -37,7 +39,7 @@ pub struct TableBuilder {
 }
 
 impl TableBuilder {
-    pub fn new(config: Arc<Config>, fd: Arc<std::fs::File>, ring: rio::Rio) -> Self {
+    pub fn new(config: Arc<Config>, fd: Arc<std::fs::File>, io: Arc<dyn IoBackend>) -> Self {
         let data_block = BlockBuilder::new(config.clone());
         let index_block = BlockBuilder::new(config.clone());
 
@@ -45,21 +47,30 @@ impl TableBuilder {
         let mut filter_block = None;
         if let Some(ref filter) = filter_policy {
             let mut builder = FilterBlockBuilder::new(filter.clone());
-            builder.start_block(0);
+            builder.start_block(0).expect("first block starts at 0");
             filter_block = Some(builder);
         }
 
-        let this = Self {
+        if config.preallocate_size > 0 {
+            // best effort, the table is written the same without it
+            if let Err(e) = preallocate(&fd, config.preallocate_size) {
+                tracing::warn!("preallocate table file: {}", e);
+            }
+        }
+
+        Self {
             config,
 
             fd,
-            ring,
+            io,
             append_offset: 0,
 
             data_block,
             index_block,
+            first_key: Vec::new(),
             last_key: Vec::new(),
             entries_count: 0,
+            data_blocks_count: 0,
 
             closed: false,
 
@@ -67,9 +78,17 @@ impl TableBuilder {
 
             pending_index_entry: false,
             pending_handle: BlockHandle::new(0, 0),
-        };
+        }
+    }
 
-        todo!()
+    /// Create a builder whose block settings override those in `config`.
+    pub fn with_options(
+        config: Arc<Config>,
+        opts: &TableOptions,
+        fd: Arc<std::fs::File>,
+        io: Arc<dyn IoBackend>,
+    ) -> Self {
+        Self::new(config.with_table_options(opts), fd, io)
     }
 
     pub fn add(&mut self, key: &[u8], value: &[u8]) -> DBResult<()> {
@@ -77,7 +96,20 @@ impl TableBuilder {
 
         if self.entries_count > 0 {
             // 需要输入 key 必须大于 last_key，按照给定的比较顺序
-            assert!(self.config.comparator.compare(key, &self.last_key).is_ge());
+            let ord = self.config.comparator.compare(key, &self.last_key);
+            assert!(ord.is_ge());
+            if ord.is_eq() {
+                match self.config.duplicate_keys {
+                    DuplicateKeyPolicy::Keep => {}
+                    DuplicateKeyPolicy::Skip => return Ok(()),
+                    DuplicateKeyPolicy::Reject => {
+                        return Err(DBError::InvalidArgument(format!(
+                            "duplicate key: {:?}",
+                            key
+                        )))
+                    }
+                }
+            }
         }
 
         if self.pending_index_entry {
@@ -96,6 +128,9 @@ impl TableBuilder {
             filter_block.add_key(key);
         }
 
+        if self.entries_count == 0 {
+            self.first_key.extend(key);
+        }
         self.last_key.clear();
         self.last_key.extend(key);
         self.entries_count += 1;
@@ -115,10 +150,10 @@ impl TableBuilder {
         }
 
         assert!(!self.pending_index_entry);
-        let raw_block = self.data_block.finish();
+        let raw_block = self.data_block.finish()?;
         let compress_block = do_compress(raw_block, self.config.compresstion_type)?;
         write_raw_block(
-            &self.ring,
+            self.io.as_ref(),
             &self.fd,
             &mut self.append_offset,
             &compress_block,
@@ -127,9 +162,10 @@ impl TableBuilder {
         )?;
 
         self.data_block.reset();
+        self.data_blocks_count += 1;
         self.pending_index_entry = true;
         if let Some(filter_builder) = &mut self.filter_block {
-            filter_builder.start_block(self.append_offset);
+            filter_builder.start_block(self.append_offset)?;
         }
 
         return Ok(());
@@ -145,7 +181,7 @@ impl TableBuilder {
         if let Some(b) = &mut self.filter_block {
             let filter_raw_block = b.finish();
             write_raw_block(
-                &self.ring,
+                self.io.as_ref(),
                 &self.fd,
                 &mut self.append_offset,
                 filter_raw_block,
@@ -160,13 +196,13 @@ impl TableBuilder {
         let meta_raw_block = if self.filter_block.is_some() {
             let filter_key = self.config.filter_policy.as_ref().unwrap().name();
             meta_block_builder.add(filter_key.as_bytes(), &filter_block_handler.encode());
-            meta_block_builder.finish()
+            meta_block_builder.finish()?
         } else {
-            meta_block_builder.finish()
+            meta_block_builder.finish()?
         };
         let meta_block = do_compress(meta_raw_block, self.config.compresstion_type)?;
         write_raw_block(
-            &self.ring,
+            self.io.as_ref(),
             &self.fd,
             &mut self.append_offset,
             &meta_block,
@@ -182,11 +218,11 @@ impl TableBuilder {
             self.index_block.add(&sep, &handle_encoding);
             self.pending_index_entry = false;
         }
-        let index_raw_block = self.index_block.finish();
+        let index_raw_block = self.index_block.finish()?;
         let mut index_block_handle = BlockHandle::new(0, 0);
         let index_block = do_compress(index_raw_block, self.config.compresstion_type)?;
         write_raw_block(
-            &self.ring,
+            self.io.as_ref(),
             &self.fd,
             &mut self.append_offset,
             &index_block,
@@ -198,23 +234,42 @@ impl TableBuilder {
         // footer
         let footer = Footer::new(meta_block_handle, index_block_handle).encode();
 
-        let comp = self
-            .ring
-            .write_at(self.fd.as_ref(), &footer, self.append_offset);
+        write_exact(
+            self.io.as_ref(),
+            &self.fd,
+            &footer,
+            self.append_offset,
+            "footer",
+        )?;
         self.append_offset += footer.len() as u64;
 
+        self.io.fsync(self.fd.as_ref())?;
         Ok(())
     }
 
     pub fn abandon(&mut self) {
         assert!(!self.closed);
-        self.closed;
+        self.closed = true;
     }
 
     pub fn entries_count(&self) -> u64 {
         self.entries_count
     }
 
+    /// The first key added, empty if the table has no entries.
+    pub fn smallest_key(&self) -> &[u8] {
+        &self.first_key
+    }
+
+    /// The last key added, empty if the table has no entries.
+    pub fn largest_key(&self) -> &[u8] {
+        &self.last_key
+    }
+
+    pub fn data_blocks_count(&self) -> u64 {
+        self.data_blocks_count
+    }
+
     pub fn file_size(&self) -> u64 {
         self.append_offset
     }
@@ -231,33 +286,261 @@ fn do_compress(raw_block_content: &[u8], compression_type: CompressionType) -> D
 }
 
 fn write_raw_block(
-    ring: &rio::Rio,
+    io: &dyn IoBackend,
     fd: &std::fs::File,
     append_offset: &mut u64,
     content: &[u8],
     compression_type: CompressionType,
     handle: &mut BlockHandle,
 ) -> DBResult<()> {
-    let comp1 = ring.write_at(fd, &content, *append_offset);
-    *append_offset += content.len() as u64;
     handle.set_offset(*append_offset);
     handle.set_size(content.len() as u64);
+    write_exact(io, fd, content, *append_offset, "block")?;
+    *append_offset += content.len() as u64;
 
     let mut trailer = vec![compression_type as u8];
     let mut hasher = crc32fast::Hasher::new();
     hasher.update(content);
     hasher.update(&trailer);
     let crc = hasher.finalize();
-    trailer.put_u32_le(crc);
+    encoding::put_u32(&mut trailer, crc);
     assert_eq!(trailer.len(), 5);
 
-    let comp2 = ring.write_at(fd, &trailer, *append_offset);
+    write_exact(io, fd, &trailer, *append_offset, "block trailer")?;
     *append_offset += trailer.len() as u64;
 
-    let count = comp1.wait()?;
-    assert_eq!(count, content.len());
-    let count = comp2.wait()?;
-    assert_eq!(count, trailer.len());
+    Ok(())
+}
 
+// a short write is an error rather than a panic, the builder can be dropped after it
+fn write_exact(
+    io: &dyn IoBackend,
+    fd: &std::fs::File,
+    buf: &[u8],
+    offset: u64,
+    what: &str,
+) -> DBResult<()> {
+    let count = io.write_at(fd, buf, offset)?;
+    if count != buf.len() {
+        return Err(DBError::IO(std::io::Error::new(
+            std::io::ErrorKind::WriteZero,
+            format!("short {} write: {} of {} bytes", what, count, buf.len()),
+        )));
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        os::unix::fs::FileExt,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use crate::{
+        config::{ConfigBuilder, DuplicateKeyPolicy, TableOptions},
+        error::DBError,
+        io::{default_backend, IoBackend, StdBackend},
+        table::block_handler::{Footer, FOOTER_ENCODE_LEN},
+        utils::testing::TempFile,
+    };
+
+    use super::TableBuilder;
+
+    fn add_duplicates(name: &str, policy: DuplicateKeyPolicy) -> u64 {
+        let config = ConfigBuilder::default().duplicate_keys(policy).build();
+        let file = TempFile::new(name);
+        let mut builder = TableBuilder::new(config, file.shared(), default_backend());
+        for key in ["a", "b", "b", "b", "c"] {
+            builder.add(key.as_bytes(), b"v").unwrap();
+        }
+        builder.entries_count()
+    }
+
+    #[test]
+    fn keep_duplicate_keys() {
+        assert_eq!(
+            add_duplicates("keep_duplicate_keys", DuplicateKeyPolicy::Keep),
+            5
+        );
+    }
+
+    #[test]
+    fn skip_duplicate_keys() {
+        assert_eq!(
+            add_duplicates("skip_duplicate_keys", DuplicateKeyPolicy::Skip),
+            3
+        );
+    }
+
+    #[test]
+    fn reject_duplicate_keys() {
+        let config = ConfigBuilder::default()
+            .duplicate_keys(DuplicateKeyPolicy::Reject)
+            .build();
+        let file = TempFile::new("reject_duplicate_keys");
+        let mut builder = TableBuilder::new(config, file.shared(), default_backend());
+        builder.add(b"a", b"v").unwrap();
+        builder.add(b"b", b"v").unwrap();
+        assert!(matches!(
+            builder.add(b"b", b"v"),
+            Err(DBError::InvalidArgument(_))
+        ));
+        builder.add(b"c", b"v").unwrap();
+        assert_eq!(builder.entries_count(), 3);
+        builder.finish().unwrap();
+    }
+
+    #[test]
+    fn override_block_size() {
+        let config = ConfigBuilder::default().block_size(4096).build();
+        let build = |name, opts: &TableOptions| {
+            let file = TempFile::new(name);
+            let mut builder =
+                TableBuilder::with_options(config.clone(), opts, file.shared(), default_backend());
+            for i in 0..1000 {
+                let key = format!("key{:08}", i);
+                builder.add(key.as_bytes(), &[0; 100]).unwrap();
+            }
+            builder.flush().unwrap();
+            builder.data_blocks_count()
+        };
+
+        let small = build(
+            "override_block_size_small",
+            &TableOptions {
+                block_size: Some(1024),
+                ..Default::default()
+            },
+        );
+        let base = build("override_block_size_base", &TableOptions::default());
+        assert!(small >= base * 3, "small: {}, base: {}", small, base);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn preallocate_table_file() {
+        use std::os::unix::fs::MetadataExt;
+
+        use crate::utils::fs::preallocate;
+
+        // the builder goes on without it where the filesystem can't preallocate
+        if !preallocate(&TempFile::new("preallocate_table_file_probe"), 1 << 20).unwrap() {
+            return;
+        }
+
+        let file = TempFile::new("preallocate_table_file");
+        let opts = TableOptions {
+            preallocate_size: Some(1 << 20),
+            ..Default::default()
+        };
+        let config = ConfigBuilder::default().build();
+        let mut builder =
+            TableBuilder::with_options(config, &opts, file.shared(), default_backend());
+        let meta = file.metadata().unwrap();
+        assert_eq!(meta.len(), 0);
+        assert!(meta.blocks() * 512 >= 1 << 20, "blocks: {}", meta.blocks());
+
+        for i in 0..100 {
+            let key = format!("key{:08}", i);
+            builder.add(key.as_bytes(), key.as_bytes()).unwrap();
+        }
+        builder.finish().unwrap();
+        // the file keeps its written size, the footer stays at the end
+        assert_eq!(file.metadata().unwrap().len(), builder.file_size());
+    }
+
+    #[test]
+    fn key_range() {
+        let config = ConfigBuilder::default().build();
+        let file = TempFile::new("key_range");
+        let mut builder = TableBuilder::new(config, file.shared(), default_backend());
+        assert!(builder.smallest_key().is_empty());
+        assert!(builder.largest_key().is_empty());
+
+        for i in 10..1000 {
+            let key = format!("key{:08}", i);
+            builder.add(key.as_bytes(), b"v").unwrap();
+        }
+        builder.finish().unwrap();
+        assert_eq!(builder.smallest_key(), b"key00000010");
+        assert_eq!(builder.largest_key(), b"key00000999");
+    }
+
+    #[test]
+    fn finish_writes_footer() {
+        let file = TempFile::new("finish_writes_footer");
+        let config = ConfigBuilder::default().build();
+        let mut builder = TableBuilder::new(config, file.shared(), default_backend());
+        for i in 0..100 {
+            let key = format!("key{:08}", i);
+            builder.add(key.as_bytes(), key.as_bytes()).unwrap();
+        }
+        builder.finish().unwrap();
+
+        let len = file.metadata().unwrap().len();
+        assert_eq!(len, builder.file_size());
+        let mut footer = vec![0; FOOTER_ENCODE_LEN];
+        file.read_exact_at(&mut footer, len - FOOTER_ENCODE_LEN as u64)
+            .unwrap();
+        assert!(Footer::decode(&footer).is_ok());
+    }
+
+    // writes through the std backend, but never past `limit` bytes in total
+    struct ShortWriteBackend {
+        limit: usize,
+        written: AtomicUsize,
+    }
+
+    impl IoBackend for ShortWriteBackend {
+        fn read_at(&self, fd: &File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+            StdBackend.read_at(fd, buf, offset)
+        }
+
+        fn write_at(&self, fd: &File, buf: &[u8], offset: u64) -> std::io::Result<usize> {
+            let written = self.written.load(Ordering::SeqCst);
+            let len = buf.len().min(self.limit.saturating_sub(written));
+            let count = StdBackend.write_at(fd, &buf[..len], offset)?;
+            self.written.fetch_add(count, Ordering::SeqCst);
+            Ok(count)
+        }
+
+        fn fsync(&self, fd: &File) -> std::io::Result<()> {
+            StdBackend.fsync(fd)
+        }
+    }
+
+    #[test]
+    fn short_write() {
+        let build = |name, limit| {
+            let config = ConfigBuilder::default().block_size(256).build();
+            let io = Arc::new(ShortWriteBackend {
+                limit,
+                written: AtomicUsize::new(0),
+            });
+            let file = TempFile::new(name);
+            let mut builder = TableBuilder::new(config, file.shared(), io);
+            for i in 0..100 {
+                let key = format!("key{:08}", i);
+                builder.add(key.as_bytes(), key.as_bytes())?;
+            }
+            builder.finish()?;
+            Ok::<_, DBError>(builder.file_size() as usize)
+        };
+
+        let size = build("short_write", usize::MAX).unwrap();
+        // a short data block, the first write, and a short footer, the last one
+        for limit in [10, size - 1] {
+            match build("short_write", limit) {
+                Err(DBError::IO(e)) => {
+                    assert_eq!(e.kind(), std::io::ErrorKind::WriteZero, "{}", e)
+                }
+                res => panic!("limit {}: {:?}", limit, res),
+            }
+        }
+    }
+}
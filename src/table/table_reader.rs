@@ -0,0 +1,678 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::{
+    comparator::Comparator,
+    config::{CompressionType, Config},
+    error::{Corruption, CorruptionKind, DBError, DBResult},
+    io::{AccessPattern, IoBackend},
+    iterator::Iterator,
+    utils::encoding,
+};
+
+use super::{
+    block::Block,
+    block_handler::{BlockHandle, Footer, FOOTER_ENCODE_LEN},
+    filter_block::FilterBlockReader,
+    two_level_iterator::TwoLevelIterator,
+};
+
+// | compression type 1b | crc32 4b |
+pub(crate) const BLOCK_TRAILER_LEN: usize = 5;
+
+pub struct Table {
+    config: Arc<Config>,
+    fd: Arc<std::fs::File>,
+    io: Arc<dyn IoBackend>,
+    // named by corruption errors, if the table was opened by its path
+    path: Option<PathBuf>,
+    // blocks end before the footer
+    data_end: u64,
+
+    index_block: Block,
+    filter: Option<FilterBlockReader>,
+
+    data_block_reads: AtomicU64,
+}
+
+impl Table {
+    /// Open a table of `file_size` bytes. The filter block is loaded only if the
+    /// table was built with the filter policy configured in `config`, a table
+    /// built with another policy or none is opened without a filter. With
+    /// `paranoid_checks` every data block is read and checked before returning.
+    pub fn open(
+        config: Arc<Config>,
+        fd: Arc<std::fs::File>,
+        io: Arc<dyn IoBackend>,
+        file_size: u64,
+    ) -> DBResult<Self> {
+        Self::open_with_path(config, fd, io, file_size, None)
+    }
+
+    /// Open the table file at `path`, corruption errors name the file.
+    pub fn open_file(
+        config: Arc<Config>,
+        path: impl AsRef<Path>,
+        io: Arc<dyn IoBackend>,
+    ) -> DBResult<Self> {
+        let path = path.as_ref();
+        let fd = std::fs::File::open(path)?;
+        let file_size = fd.metadata()?.len();
+        Self::open_with_path(
+            config,
+            Arc::new(fd),
+            io,
+            file_size,
+            Some(path.to_path_buf()),
+        )
+        .map_err(|e| e.in_file(path))
+    }
+
+    fn open_with_path(
+        config: Arc<Config>,
+        fd: Arc<std::fs::File>,
+        io: Arc<dyn IoBackend>,
+        file_size: u64,
+        path: Option<PathBuf>,
+    ) -> DBResult<Self> {
+        if file_size < FOOTER_ENCODE_LEN as u64 {
+            return Err(Corruption::new(
+                CorruptionKind::Truncated,
+                format!("file too short to be a table: {} bytes", file_size),
+            )
+            .into());
+        }
+        let footer = read_exact(
+            io.as_ref(),
+            &fd,
+            file_size - FOOTER_ENCODE_LEN as u64,
+            FOOTER_ENCODE_LEN,
+        )?;
+        let footer = Footer::decode(&footer)?;
+
+        let data_end = file_size - FOOTER_ENCODE_LEN as u64;
+        let index_block =
+            Block::new(read_block(io.as_ref(), &fd, footer.index_handle(), data_end)?.into())?;
+        let mut this = Self {
+            config,
+            fd,
+            io,
+            path,
+            data_end,
+            index_block,
+            filter: None,
+            data_block_reads: AtomicU64::new(0),
+        };
+        this.read_meta(footer.meta_index_handle())?;
+        if this.config.paranoid_checks {
+            this.verify_blocks(footer.meta_index_handle())?;
+        }
+        Ok(this)
+    }
+
+    // read every data block, the meta index block and every block it names, filters
+    // of any policy included. read_block fails on the first block whose crc does not
+    // match, naming its offset. The index block was checked when it was read.
+    fn verify_blocks(&self, meta_handle: BlockHandle) -> DBResult<()> {
+        let meta = Block::new(self.read_block(meta_handle)?.into())?;
+        for block in [&self.index_block, &meta] {
+            let mut iter = block.iter(self.config.comparator.clone());
+            iter.seek_to_first();
+            while iter.is_valid() {
+                let (handle, _) = BlockHandle::decode_from(iter.value())?;
+                self.read_block(handle)?;
+                iter.next();
+            }
+            iter.status()?;
+        }
+        Ok(())
+    }
+
+    // the meta index block maps a filter policy name to its filter block
+    fn read_meta(&mut self, handle: BlockHandle) -> DBResult<()> {
+        let policy = match &self.config.filter_policy {
+            Some(policy) => policy.clone(),
+            None => return Ok(()),
+        };
+
+        let meta = Block::new(self.read_block(handle)?.into())?;
+        let mut iter = meta.iter(self.config.comparator.clone());
+        iter.seek_to_first();
+        while iter.is_valid() {
+            if iter.key() == policy.name().as_bytes() {
+                let (filter_handle, _) = BlockHandle::decode_from(iter.value())?;
+                let data = self.read_block(filter_handle)?;
+                self.filter = Some(FilterBlockReader::new(policy, data));
+                return Ok(());
+            }
+            iter.next();
+        }
+        iter.status()
+    }
+
+    fn read_block(&self, handle: BlockHandle) -> DBResult<Vec<u8>> {
+        read_block(self.io.as_ref(), &self.fd, handle, self.data_end).map_err(|e| self.in_file(e))
+    }
+
+    fn in_file(&self, err: DBError) -> DBError {
+        match &self.path {
+            Some(path) => err.in_file(path),
+            None => err,
+        }
+    }
+
+    pub fn index_block(&self) -> &Block {
+        &self.index_block
+    }
+
+    pub(crate) fn comparator(&self) -> &Arc<dyn Comparator> {
+        &self.config.comparator
+    }
+
+    pub fn read_data_block(&self, handle: BlockHandle) -> DBResult<Block> {
+        self.data_block_reads.fetch_add(1, Ordering::Relaxed);
+        Block::new(self.read_block(handle)?.into()).map_err(|e| self.in_file(e))
+    }
+
+    /// Number of data blocks read from the file so far.
+    pub fn data_block_reads(&self) -> u64 {
+        self.data_block_reads.load(Ordering::Relaxed)
+    }
+
+    pub fn iter(&self) -> TwoLevelIterator<'_> {
+        self.range_iter(None, None)
+    }
+
+    /// Iterate the keys in `[start, end]`, either bound may be open. Only the data
+    /// blocks that may overlap the range are read, which compaction relies on when
+    /// an input table only partially overlaps the output range.
+    pub fn range_iter(&self, start: Option<&[u8]>, end: Option<&[u8]>) -> TwoLevelIterator<'_> {
+        TwoLevelIterator::new(self, start, end)
+    }
+
+    /// Hint how the file is about to be read, `Sequential` suits full scans and
+    /// compaction inputs. Ignored where the platform has no such hint.
+    pub fn set_access_pattern(&self, pattern: AccessPattern) -> DBResult<()> {
+        self.io.advise(&self.fd, pattern)?;
+        Ok(())
+    }
+
+    pub fn filter(&self) -> Option<&FilterBlockReader> {
+        self.filter.as_ref()
+    }
+
+    /// Whether the data block at `block_offset` may hold `key`, always true without a filter.
+    pub fn key_may_match(&self, block_offset: u64, key: &[u8]) -> bool {
+        match &self.filter {
+            Some(filter) => filter.key_may_match(block_offset, key),
+            None => true,
+        }
+    }
+}
+
+fn read_exact(
+    io: &dyn IoBackend,
+    fd: &std::fs::File,
+    offset: u64,
+    len: usize,
+) -> DBResult<Vec<u8>> {
+    let mut buf = vec![0; len];
+    let count = io.read_at(fd, &mut buf, offset)?;
+    if count != len {
+        return Err(Corruption::new(
+            CorruptionKind::Truncated,
+            format!("short table read: {} of {} bytes", count, len),
+        )
+        .at(offset)
+        .into());
+    }
+    Ok(buf)
+}
+
+// read the content of a block and check its trailer. Handles carry no crc, so one
+// reaching past `data_end` is rejected before a buffer of its size is allocated.
+fn read_block(
+    io: &dyn IoBackend,
+    fd: &std::fs::File,
+    handle: BlockHandle,
+    data_end: u64,
+) -> DBResult<Vec<u8>> {
+    let end = handle
+        .offset()
+        .checked_add(handle.size())
+        .and_then(|end| end.checked_add(BLOCK_TRAILER_LEN as u64));
+    if !matches!(end, Some(end) if end <= data_end) {
+        return Err(Corruption::new(
+            CorruptionKind::BadOffset,
+            format!(
+                "block of {} bytes runs past the end of the data at {}",
+                handle.size(),
+                data_end
+            ),
+        )
+        .at(handle.offset())
+        .into());
+    }
+
+    let len = handle.size() as usize;
+    let mut buf = read_exact(io, fd, handle.offset(), len + BLOCK_TRAILER_LEN)?;
+
+    let crc = encoding::get_u32(&buf[len + 1..]);
+    if crc32fast::hash(&buf[..len + 1]) != crc {
+        return Err(
+            Corruption::new(CorruptionKind::ChecksumMismatch, "block checksum mismatch")
+                .at(handle.offset())
+                .into(),
+        );
+    }
+    if buf[len] != CompressionType::None as u8 {
+        return Err(Corruption::new(
+            CorruptionKind::BadType,
+            format!("unsupported block compression type {}", buf[len]),
+        )
+        .at(handle.offset())
+        .into());
+    }
+
+    buf.truncate(len);
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        os::unix::fs::FileExt,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::{
+        config::{Config, ConfigBuilder},
+        error::{Corruption, CorruptionKind, DBError, DBResult},
+        filter::FilterPolicy,
+        io::{default_backend, AccessPattern, IoBackend, StdBackend},
+        iterator::Iterator,
+        table::{
+            block::Block,
+            block_handler::{BlockHandle, Footer, FOOTER_ENCODE_LEN},
+            table_builder::TableBuilder,
+        },
+        utils::{comparators::BytewiseComparator, encoding, hash::basic_hash, testing::TempFile},
+    };
+
+    use super::{read_block, Table};
+
+    struct TestHashFilter(&'static str);
+
+    impl FilterPolicy for TestHashFilter {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn create_filter(&self, keys: &[Vec<u8>]) -> Vec<u8> {
+            keys.iter()
+                .flat_map(|key| basic_hash(key, 1).to_le_bytes())
+                .collect()
+        }
+
+        fn may_contain(&self, filter: &[u8], key: &[u8]) -> bool {
+            let h = basic_hash(key, 1).to_le_bytes();
+            filter.chunks_exact(4).any(|c| c == h)
+        }
+    }
+
+    fn build_table(count: usize, name: &str, config: Arc<Config>) -> (TempFile, u64) {
+        let fd = TempFile::new(name);
+        let mut builder = TableBuilder::new(config, fd.shared(), default_backend());
+        for i in 0..count {
+            let key = format!("key{:08}", i);
+            builder.add(key.as_bytes(), key.as_bytes()).unwrap();
+        }
+        builder.finish().unwrap();
+        let size = builder.file_size();
+        (fd, size)
+    }
+
+    fn with_filter(name: &'static str) -> Arc<Config> {
+        ConfigBuilder::default()
+            .filter_policy(Arc::new(TestHashFilter(name)))
+            .build()
+    }
+
+    #[test]
+    fn open_with_filter() {
+        let (fd, size) = build_table(100, "open_with_filter", with_filter("test"));
+        let table = Table::open(with_filter("test"), fd.shared(), default_backend(), size).unwrap();
+        assert!(table.filter().is_some());
+        assert!(table.key_may_match(0, b"key00000000"));
+        assert!(table.key_may_match(0, b"key00000099"));
+        assert!(!table.key_may_match(0, b"missing"));
+    }
+
+    #[test]
+    fn open_without_filter() {
+        let (fd, size) = build_table(100, "open_without_filter", ConfigBuilder::default().build());
+        let table = Table::open(with_filter("test"), fd.shared(), default_backend(), size).unwrap();
+        assert!(table.filter().is_none());
+        assert!(table.key_may_match(0, b"missing"));
+
+        let (fd, size) = build_table(100, "open_ignore_filter", with_filter("test"));
+        let config = ConfigBuilder::default().build();
+        let table = Table::open(config, fd.shared(), default_backend(), size).unwrap();
+        assert!(table.filter().is_none());
+    }
+
+    #[test]
+    fn skip_other_filter_policy() {
+        let (fd, size) = build_table(100, "skip_other_filter_policy", with_filter("test"));
+        let table =
+            Table::open(with_filter("other"), fd.shared(), default_backend(), size).unwrap();
+        assert!(table.filter().is_none());
+    }
+
+    #[test]
+    fn open_truncated_table() {
+        let config = ConfigBuilder::default().build();
+        let (fd, _) = build_table(100, "open_truncated_table", config.clone());
+        assert!(Table::open(config, fd.shared(), default_backend(), 10).is_err());
+    }
+
+    fn collect_keys(iter: &mut impl Iterator) -> Vec<String> {
+        let mut keys = vec![];
+        iter.seek_to_first();
+        while iter.is_valid() {
+            assert_eq!(iter.key(), iter.value());
+            keys.push(String::from_utf8(iter.key().to_vec()).unwrap());
+            iter.next();
+        }
+        iter.status().unwrap();
+        keys
+    }
+
+    fn keys(range: impl std::iter::Iterator<Item = usize>) -> Vec<String> {
+        range.map(|i| format!("key{:08}", i)).collect()
+    }
+
+    fn open_multi_block(name: &str) -> (TempFile, Table) {
+        let config = ConfigBuilder::default().block_size(256).build();
+        let (fd, size) = build_table(1000, name, config.clone());
+        let table = Table::open(config, fd.shared(), default_backend(), size).unwrap();
+        (fd, table)
+    }
+
+    #[test]
+    fn iterate_table() {
+        let (_fd, table) = open_multi_block("iterate_table");
+        let mut iter = table.iter();
+        assert_eq!(collect_keys(&mut iter), keys(0..1000));
+
+        let mut reversed = vec![];
+        iter.seek_to_last();
+        while iter.is_valid() {
+            reversed.push(String::from_utf8(iter.key().to_vec()).unwrap());
+            iter.prev();
+        }
+        assert_eq!(reversed, keys((0..1000).rev()));
+
+        iter.seek(b"key00000500");
+        assert_eq!(iter.key(), b"key00000500");
+        iter.seek(b"key00000500a");
+        assert_eq!(iter.key(), b"key00000501");
+        iter.seek(b"zzz");
+        assert!(!iter.is_valid());
+    }
+
+    #[test]
+    fn range_iter_reads_overlapping_blocks() {
+        let (_fd, table) = open_multi_block("range_iter_reads_overlapping_blocks");
+        collect_keys(&mut table.iter());
+        let all_blocks = table.data_block_reads();
+        assert!(all_blocks > 10, "blocks: {}", all_blocks);
+
+        let mut iter = table.range_iter(
+            Some(b"key00000500".as_slice()),
+            Some(b"key00000509".as_slice()),
+        );
+        assert_eq!(collect_keys(&mut iter), keys(500..510));
+        let range_blocks = table.data_block_reads() - all_blocks;
+        assert!(range_blocks <= 2, "blocks: {}", range_blocks);
+
+        let mut reversed = vec![];
+        iter.seek_to_last();
+        while iter.is_valid() {
+            reversed.push(String::from_utf8(iter.key().to_vec()).unwrap());
+            iter.prev();
+        }
+        assert_eq!(reversed, keys((500..510).rev()));
+
+        // open bounds and bounds between keys
+        let mut iter = table.range_iter(None, Some(b"key00000009a".as_slice()));
+        assert_eq!(collect_keys(&mut iter), keys(0..10));
+        let mut iter = table.range_iter(Some(b"key00000990a".as_slice()), None);
+        assert_eq!(collect_keys(&mut iter), keys(991..1000));
+        let mut iter = table.range_iter(Some(b"a".as_slice()), Some(b"b".as_slice()));
+        assert!(collect_keys(&mut iter).is_empty());
+    }
+
+    // the handle of the `n`th data block
+    fn data_block_handle(table: &Table, n: usize) -> BlockHandle {
+        let mut iter = table.index_block().iter(table.comparator().clone());
+        iter.seek_to_first();
+        for _ in 0..n {
+            iter.next();
+        }
+        BlockHandle::decode_from(iter.value()).unwrap().0
+    }
+
+    fn flip_byte(fd: &File, offset: u64) {
+        let mut byte = [0];
+        fd.read_exact_at(&mut byte, offset).unwrap();
+        fd.write_all_at(&[byte[0] ^ 0x5a], offset).unwrap();
+    }
+
+    fn corruption_at(res: DBResult<Table>) -> (CorruptionKind, Option<u64>) {
+        match res {
+            Err(DBError::Corruption(c)) => (c.kind, c.offset),
+            Err(e) => panic!("expected corruption: {}", e),
+            Ok(_) => panic!("expected corruption"),
+        }
+    }
+
+    #[test]
+    fn paranoid_open() {
+        let config = ConfigBuilder::default().block_size(256).build();
+        let (fd, size) = build_table(1000, "paranoid_open", config.clone());
+        let table = Table::open(config.clone(), fd.shared(), default_backend(), size).unwrap();
+        let handle = data_block_handle(&table, 3);
+        flip_byte(&fd, handle.offset() + 10);
+
+        let paranoid = ConfigBuilder::default().paranoid_checks(true).build();
+        let res = Table::open(paranoid, fd.shared(), default_backend(), size);
+        assert_eq!(
+            corruption_at(res),
+            (CorruptionKind::ChecksumMismatch, Some(handle.offset()))
+        );
+
+        // the corruption only shows once the bad block is read
+        let table = Table::open(config, fd.shared(), default_backend(), size).unwrap();
+        let mut iter = table.iter();
+        iter.seek_to_first();
+        while iter.is_valid() {
+            iter.next();
+        }
+        assert!(matches!(iter.status(), Err(DBError::Corruption(_))));
+    }
+
+    #[test]
+    fn corruption_names_file() {
+        let config = ConfigBuilder::default().block_size(256).build();
+        let (fd, size) = build_table(1000, "corruption_names_file", config.clone());
+        let table = Table::open_file(config.clone(), fd.path(), default_backend()).unwrap();
+        let handle = data_block_handle(&table, 3);
+        flip_byte(&fd, handle.offset() + 10);
+
+        let file_of = |res: DBResult<()>| match res {
+            Err(DBError::Corruption(c)) => c.file,
+            Err(e) => panic!("expected corruption: {}", e),
+            Ok(_) => panic!("expected corruption"),
+        };
+        let path = Some(fd.path().to_path_buf());
+        assert_eq!(file_of(table.read_data_block(handle).map(|_| ())), path);
+        let paranoid = ConfigBuilder::default().paranoid_checks(true).build();
+        let res = Table::open_file(paranoid.clone(), fd.path(), default_backend());
+        assert_eq!(file_of(res.map(|_| ())), path);
+
+        // a table opened from a file handle does not know its path
+        let res = Table::open(paranoid, fd.shared(), default_backend(), size);
+        assert_eq!(file_of(res.map(|_| ())), None);
+    }
+
+    #[test]
+    fn paranoid_open_checks_filter() {
+        let (fd, size) = build_table(100, "paranoid_open_checks_filter", with_filter("test"));
+        let data_end = size - FOOTER_ENCODE_LEN as u64;
+        let mut footer = vec![0; FOOTER_ENCODE_LEN];
+        fd.read_exact_at(&mut footer, data_end).unwrap();
+        let meta_handle = Footer::decode(&footer).unwrap().meta_index_handle();
+        let meta = read_block(&StdBackend, &fd, meta_handle, data_end).unwrap();
+        let meta = Block::new(meta.into()).unwrap();
+        let mut iter = meta.iter(Arc::new(BytewiseComparator));
+        iter.seek_to_first();
+        let (filter_handle, _) = BlockHandle::decode_from(iter.value()).unwrap();
+        flip_byte(&fd, filter_handle.offset());
+
+        // the filter of a policy the config does not name is checked all the same
+        let paranoid = ConfigBuilder::default().paranoid_checks(true).build();
+        let res = Table::open(paranoid, fd.shared(), default_backend(), size);
+        assert_eq!(
+            corruption_at(res),
+            (
+                CorruptionKind::ChecksumMismatch,
+                Some(filter_handle.offset())
+            )
+        );
+
+        flip_byte(&fd, filter_handle.offset());
+        flip_byte(&fd, meta_handle.offset());
+        let paranoid = ConfigBuilder::default().paranoid_checks(true).build();
+        let res = Table::open(paranoid, fd.shared(), default_backend(), size);
+        assert_eq!(
+            corruption_at(res),
+            (CorruptionKind::ChecksumMismatch, Some(meta_handle.offset()))
+        );
+    }
+
+    #[derive(Default)]
+    struct AdviceRecorder(Mutex<Vec<AccessPattern>>);
+
+    impl IoBackend for AdviceRecorder {
+        fn read_at(&self, fd: &File, buf: &mut [u8], offset: u64) -> std::io::Result<usize> {
+            StdBackend.read_at(fd, buf, offset)
+        }
+
+        fn write_at(&self, fd: &File, buf: &[u8], offset: u64) -> std::io::Result<usize> {
+            StdBackend.write_at(fd, buf, offset)
+        }
+
+        fn fsync(&self, fd: &File) -> std::io::Result<()> {
+            StdBackend.fsync(fd)
+        }
+
+        fn advise(&self, fd: &File, pattern: AccessPattern) -> std::io::Result<()> {
+            self.0.lock().unwrap().push(pattern);
+            StdBackend.advise(fd, pattern)
+        }
+    }
+
+    #[test]
+    fn sequential_access_pattern() {
+        let config = ConfigBuilder::default().build();
+        let (fd, size) = build_table(100, "sequential_access_pattern", config.clone());
+        let io = Arc::new(AdviceRecorder::default());
+        let table = Table::open(config, fd.shared(), io.clone(), size).unwrap();
+        assert!(io.0.lock().unwrap().is_empty());
+
+        table.set_access_pattern(AccessPattern::Sequential).unwrap();
+        assert_eq!(collect_keys(&mut table.iter()), keys(0..100));
+        table.set_access_pattern(AccessPattern::Random).unwrap();
+        assert_eq!(
+            *io.0.lock().unwrap(),
+            [AccessPattern::Sequential, AccessPattern::Random]
+        );
+    }
+
+    fn corruption_kind(res: Result<impl std::fmt::Debug, DBError>) -> CorruptionKind {
+        match res {
+            Err(DBError::Corruption(Corruption { kind, .. })) => kind,
+            res => panic!("expected corruption: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn block_corruption_kinds() {
+        let fd = TempFile::new("block_corruption_kinds");
+
+        // a block whose trailer names an unknown compression type, with a valid crc
+        let mut block = b"abc\x07".to_vec();
+        let crc = crc32fast::hash(&block);
+        encoding::put_u32(&mut block, crc);
+        fd.write_all_at(&block, 0).unwrap();
+        let handle = BlockHandle::new(0, 3);
+        assert_eq!(
+            corruption_kind(read_block(&StdBackend, &fd, handle, u64::MAX)),
+            CorruptionKind::BadType
+        );
+
+        fd.write_all_at(b"x", 1).unwrap();
+        assert_eq!(
+            corruption_kind(read_block(&StdBackend, &fd, handle, u64::MAX)),
+            CorruptionKind::ChecksumMismatch
+        );
+
+        let handle = BlockHandle::new(0, 16);
+        assert_eq!(
+            corruption_kind(read_block(&StdBackend, &fd, handle, u64::MAX)),
+            CorruptionKind::Truncated
+        );
+        assert_eq!(
+            corruption_kind(
+                Table::open(
+                    ConfigBuilder::default().build(),
+                    fd.shared(),
+                    default_backend(),
+                    block.len() as u64
+                )
+                .map(|_| ())
+            ),
+            CorruptionKind::Truncated
+        );
+    }
+
+    #[test]
+    fn block_handle_past_end() {
+        let fd = TempFile::new("block_handle_past_end");
+        let footer = Footer::new(BlockHandle::new(0, 0), BlockHandle::new(16, u64::MAX >> 1));
+        let mut table = vec![0; 64];
+        table.extend(footer.encode());
+        fd.write_all_at(&table, 0).unwrap();
+
+        let res = Table::open(
+            ConfigBuilder::default().build(),
+            fd.shared(),
+            default_backend(),
+            table.len() as u64,
+        );
+        assert_eq!(corruption_at(res), (CorruptionKind::BadOffset, Some(16)));
+
+        let handle = BlockHandle::new(u64::MAX - 2, 2);
+        assert_eq!(
+            corruption_kind(read_block(&StdBackend, &fd, handle, u64::MAX)),
+            CorruptionKind::BadOffset
+        );
+    }
+}
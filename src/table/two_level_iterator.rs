@@ -0,0 +1,226 @@
+use std::cmp::Ordering;
+
+use crate::{
+    error::{DBError, DBResult},
+    iterator::Iterator,
+};
+
+use super::{block::BlockIter, block_handler::BlockHandle, table_reader::Table};
+
+/// Iterates a table through its index block, reading a data block only when the
+/// index moves onto it. Keys outside `[start, end]` are never returned, and the
+/// blocks past either bound are never read.
+pub struct TwoLevelIterator<'a> {
+    table: &'a Table,
+    index_iter: BlockIter,
+    data_iter: Option<BlockIter>,
+
+    start: Option<Vec<u8>>,
+    end: Option<Vec<u8>>,
+
+    status: Option<DBError>,
+}
+
+impl<'a> TwoLevelIterator<'a> {
+    pub(crate) fn new(table: &'a Table, start: Option<&[u8]>, end: Option<&[u8]>) -> Self {
+        Self {
+            table,
+            index_iter: table.index_block().iter(table.comparator().clone()),
+            data_iter: None,
+
+            start: start.map(|k| k.to_vec()),
+            end: end.map(|k| k.to_vec()),
+
+            status: None,
+        }
+    }
+
+    fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        self.table.comparator().compare(a, b)
+    }
+
+    fn before_start(&self, key: &[u8]) -> bool {
+        matches!(&self.start, Some(start) if self.compare(key, start).is_lt())
+    }
+
+    fn after_end(&self, key: &[u8]) -> bool {
+        matches!(&self.end, Some(end) if self.compare(key, end).is_gt())
+    }
+
+    fn data_valid(&self) -> bool {
+        matches!(&self.data_iter, Some(iter) if iter.is_valid())
+    }
+
+    fn init_data_block(&mut self) {
+        self.data_iter = None;
+        if !self.index_iter.is_valid() {
+            return;
+        }
+
+        let block = BlockHandle::decode_from(self.index_iter.value())
+            .and_then(|(handle, _)| self.table.read_data_block(handle));
+        match block {
+            Ok(block) => self.data_iter = Some(block.iter(self.table.comparator().clone())),
+            Err(e) => self.status = Some(e),
+        }
+    }
+
+    fn skip_empty_data_blocks_forward(&mut self) {
+        while !self.data_valid() && self.status.is_none() {
+            if !self.index_iter.is_valid() {
+                self.data_iter = None;
+                return;
+            }
+            // the next block only holds keys past this block's separator
+            if matches!(&self.end, Some(end) if self.compare(self.index_iter.key(), end).is_ge()) {
+                self.data_iter = None;
+                return;
+            }
+            self.index_iter.next();
+            self.init_data_block();
+            if let Some(iter) = &mut self.data_iter {
+                iter.seek_to_first();
+            }
+        }
+    }
+
+    fn skip_empty_data_blocks_backward(&mut self) {
+        while !self.data_valid() && self.status.is_none() {
+            if !self.index_iter.is_valid() {
+                self.data_iter = None;
+                return;
+            }
+            self.index_iter.prev();
+            // every key of the previous block is at most its separator
+            if !self.index_iter.is_valid() || self.before_start(self.index_iter.key()) {
+                self.data_iter = None;
+                return;
+            }
+            self.init_data_block();
+            if let Some(iter) = &mut self.data_iter {
+                iter.seek_to_last();
+            }
+        }
+    }
+
+    fn data_iter(&self) -> &BlockIter {
+        self.data_iter
+            .as_ref()
+            .expect("two level iterator is not valid")
+    }
+
+    fn data_iter_mut(&mut self) -> &mut BlockIter {
+        self.data_iter
+            .as_mut()
+            .expect("two level iterator is not valid")
+    }
+}
+
+impl Iterator for TwoLevelIterator<'_> {
+    fn is_valid(&self) -> bool {
+        if self.status.is_some() || !self.data_valid() {
+            return false;
+        }
+        let key = self.data_iter().key();
+        !self.before_start(key) && !self.after_end(key)
+    }
+
+    fn next(&mut self) {
+        assert!(self.is_valid(), "two level iterator is not valid");
+        self.data_iter_mut().next();
+        self.skip_empty_data_blocks_forward();
+    }
+
+    fn prev(&mut self) {
+        assert!(self.is_valid(), "two level iterator is not valid");
+        self.data_iter_mut().prev();
+        self.skip_empty_data_blocks_backward();
+    }
+
+    fn key(&self) -> &[u8] {
+        assert!(self.is_valid(), "two level iterator is not valid");
+        self.data_iter().key()
+    }
+
+    fn value(&self) -> &[u8] {
+        assert!(self.is_valid(), "two level iterator is not valid");
+        self.data_iter().value()
+    }
+
+    fn seek_to_first(&mut self) {
+        if let Some(start) = self.start.clone() {
+            self.seek(&start);
+            return;
+        }
+
+        self.index_iter.seek_to_first();
+        self.init_data_block();
+        if let Some(iter) = &mut self.data_iter {
+            iter.seek_to_first();
+        }
+        self.skip_empty_data_blocks_forward();
+    }
+
+    fn seek_to_last(&mut self) {
+        let end = match self.end.clone() {
+            Some(end) => end,
+            None => {
+                self.index_iter.seek_to_last();
+                self.init_data_block();
+                if let Some(iter) = &mut self.data_iter {
+                    iter.seek_to_last();
+                }
+                self.skip_empty_data_blocks_backward();
+                return;
+            }
+        };
+
+        // the first block whose separator reaches the end holds the last key in range
+        self.index_iter.seek(&end);
+        if !self.index_iter.is_valid() {
+            self.index_iter.seek_to_last();
+        }
+        self.init_data_block();
+        let comparator = self.table.comparator().clone();
+        if let Some(iter) = &mut self.data_iter {
+            iter.seek(&end);
+            if !iter.is_valid() {
+                iter.seek_to_last();
+            } else if comparator.compare(iter.key(), &end).is_gt() {
+                iter.prev();
+            }
+        }
+        self.skip_empty_data_blocks_backward();
+    }
+
+    fn seek(&mut self, target: &[u8]) {
+        let target = match &self.start {
+            Some(start) if self.compare(target, start).is_lt() => start.clone(),
+            _ => target.to_vec(),
+        };
+
+        self.index_iter.seek(&target);
+        self.init_data_block();
+        if let Some(iter) = &mut self.data_iter {
+            iter.seek(&target);
+        }
+        self.skip_empty_data_blocks_forward();
+    }
+
+    fn status(&mut self) -> DBResult<()> {
+        if let Some(err) = self.status.take() {
+            return Err(err);
+        }
+        self.index_iter.status()?;
+        match &mut self.data_iter {
+            Some(iter) => iter.status(),
+            None => Ok(()),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.index_iter.reset();
+        self.data_iter = None;
+        self.status = None;
+    }
+}
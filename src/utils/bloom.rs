@@ -1,23 +1,31 @@
-use super::hash::BasicHash;
+use std::sync::Arc;
 
-#[derive(Debug, Clone, Copy)]
+use super::hash::{BasicHasher, Hasher32};
+
+#[derive(Clone)]
 pub struct BloomBuilder {
     k_num: u8,
     bits_per_key: usize,
+    hasher: Arc<dyn Hasher32>,
 }
 
 impl BloomBuilder {
     pub fn new(bits_per_key: usize) -> Self {
+        Self::with_hasher(bits_per_key, Arc::new(BasicHasher))
+    }
+
+    pub fn with_hasher(bits_per_key: usize, hasher: Arc<dyn Hasher32>) -> Self {
         // ln2 * (m / n)
         Self {
             k_num: (((bits_per_key as f64 * 0.69) as usize).max(1).min(30) as u8),
             bits_per_key,
+            hasher,
         }
     }
 
     pub fn build<T>(&self, keys: &[T]) -> Vec<u8>
     where
-        T: BasicHash,
+        T: AsRef<[u8]>,
     {
         let bits = (keys.len() * self.bits_per_key).max(64);
         let bytes = (bits + 7) / 8;
@@ -27,7 +35,7 @@ impl BloomBuilder {
         filter[bytes] = self.k_num;
 
         for key in keys {
-            let mut h = Self::hash(key);
+            let mut h = self.hasher.hash32(key.as_ref());
             let delta = (h >> 17) | (h << 15);
             for _ in 0..self.k_num {
                 let bit_pos = h % bits as u32;
@@ -39,9 +47,9 @@ impl BloomBuilder {
         filter
     }
 
-    pub fn may_contain<T>(filter: &[u8], key: &T) -> bool
+    pub fn may_contain<T>(&self, filter: &[u8], key: &T) -> bool
     where
-        T: BasicHash,
+        T: AsRef<[u8]>,
     {
         if filter.len() < 1 {
             return false;
@@ -53,7 +61,7 @@ impl BloomBuilder {
         }
 
         let bits = (filter.len() - 1) * 8;
-        let mut h = Self::hash(key);
+        let mut h = self.hasher.hash32(key.as_ref());
         let delta = (h >> 17) | (h << 15);
         for _ in 0..k {
             let bit_pos = h % (bits as u32);
@@ -64,18 +72,28 @@ impl BloomBuilder {
         }
         true
     }
-
-    fn hash<T>(data: &T) -> u32
-    where
-        T: BasicHash,
-    {
-        data.gen_basic_hash()
-    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::bloom::BloomBuilder;
+    use std::sync::Arc;
+
+    use crate::utils::{
+        bloom::BloomBuilder,
+        hash::{basic_hash, Hasher32},
+    };
+
+    struct SeededHasher;
+
+    impl Hasher32 for SeededHasher {
+        fn name(&self) -> &str {
+            "seeded"
+        }
+
+        fn hash32(&self, data: &[u8]) -> u32 {
+            basic_hash(data, 1)
+        }
+    }
 
     #[test]
     fn empty_should_not_found() {
@@ -83,9 +101,9 @@ mod tests {
         let empty_ref: &[&[u8]] = &[];
         let filter = builder.build(empty_ref);
 
-        assert!(!BloomBuilder::may_contain(&filter, &"key1".as_bytes()));
-        assert!(!BloomBuilder::may_contain(&filter, &"key2".as_bytes()));
-        assert!(!BloomBuilder::may_contain(&filter, &"empty".as_bytes()));
+        assert!(!builder.may_contain(&filter, &"key1".as_bytes()));
+        assert!(!builder.may_contain(&filter, &"key2".as_bytes()));
+        assert!(!builder.may_contain(&filter, &"empty".as_bytes()));
     }
 
     #[test]
@@ -94,8 +112,8 @@ mod tests {
         let keys = vec!["key1".as_bytes(), "key2".as_bytes()];
         let filter = builder.build(&keys);
 
-        assert!(BloomBuilder::may_contain(&filter, &"key1".as_bytes()));
-        assert!(BloomBuilder::may_contain(&filter, &"key2".as_bytes()));
+        assert!(builder.may_contain(&filter, &"key1".as_bytes()));
+        assert!(builder.may_contain(&filter, &"key2".as_bytes()));
     }
 
     #[test]
@@ -112,14 +130,14 @@ mod tests {
 
             // must be contains
             for key in keys.iter() {
-                assert!(BloomBuilder::may_contain(&filter, key), "key: {:?}", key);
+                assert!(builder.may_contain(&filter, key), "key: {:?}", key);
             }
 
             // false check
             let mut hits = 0;
             for i in 0..10000 {
                 let key = (i + 1000000000).to_string().as_bytes().to_vec();
-                if BloomBuilder::may_contain(&filter, &key) {
+                if builder.may_contain(&filter, &key) {
                     hits += 1;
                 }
             }
@@ -140,4 +158,17 @@ mod tests {
             good
         );
     }
+
+    #[test]
+    fn custom_hasher() {
+        let keys: Vec<Vec<u8>> = (0..100).map(|i| i.to_string().into_bytes()).collect();
+        let basic = BloomBuilder::new(10);
+        let seeded = BloomBuilder::with_hasher(10, Arc::new(SeededHasher));
+
+        let filter = seeded.build(&keys);
+        assert_ne!(filter, basic.build(&keys));
+        for key in keys.iter() {
+            assert!(seeded.may_contain(&filter, key), "key: {:?}", key);
+        }
+    }
 }
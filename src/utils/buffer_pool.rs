@@ -0,0 +1,94 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex},
+};
+
+use bytes::BytesMut;
+
+/// A pool of read buffers, handed out as [`PooledBuf`] and returned on drop.
+pub struct BufferPool {
+    bufs: Mutex<Vec<BytesMut>>,
+    max_pooled: usize,
+}
+
+impl BufferPool {
+    pub fn new(max_pooled: usize) -> Arc<Self> {
+        Arc::new(Self {
+            bufs: Mutex::new(Vec::with_capacity(max_pooled)),
+            max_pooled,
+        })
+    }
+
+    /// Get a buffer of `len` bytes. The content is unspecified, only bytes
+    /// beyond what the reused buffer held before are zeroed.
+    pub fn get(self: &Arc<Self>, len: usize) -> PooledBuf {
+        let mut buf = self.bufs.lock().unwrap().pop().unwrap_or_default();
+        buf.resize(len, 0);
+        PooledBuf {
+            buf,
+            pool: self.clone(),
+        }
+    }
+
+    pub fn pooled(&self) -> usize {
+        self.bufs.lock().unwrap().len()
+    }
+
+    fn put(&self, buf: BytesMut) {
+        let mut bufs = self.bufs.lock().unwrap();
+        if bufs.len() < self.max_pooled {
+            bufs.push(buf);
+        }
+    }
+}
+
+pub struct PooledBuf {
+    buf: BytesMut,
+    pool: Arc<BufferPool>,
+}
+
+impl Deref for PooledBuf {
+    type Target = BytesMut;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuf {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuf {
+    fn drop(&mut self) {
+        self.pool.put(std::mem::take(&mut self.buf));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BufferPool;
+
+    #[test]
+    fn reuse_buffer() {
+        let pool = BufferPool::new(2);
+        let ptr = pool.get(4096).as_ptr();
+        for _ in 0..100 {
+            let buf = pool.get(1024);
+            assert_eq!(buf.len(), 1024);
+            assert_eq!(buf.as_ptr(), ptr);
+        }
+        assert_eq!(pool.pooled(), 1);
+    }
+
+    #[test]
+    fn bounded_pool() {
+        let pool = BufferPool::new(2);
+        let bufs: Vec<_> = (0..10).map(|_| pool.get(16)).collect();
+        assert_eq!(pool.pooled(), 0);
+        drop(bufs);
+        assert_eq!(pool.pooled(), 2);
+    }
+}
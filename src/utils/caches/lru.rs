@@ -1,7 +1,7 @@
 use std::{
     collections::HashMap,
     fmt::Debug,
-    hash::{DefaultHasher, Hash, Hasher},
+    hash::{Hash, Hasher},
     mem::MaybeUninit,
     sync::{
         atomic::{AtomicUsize, Ordering},
@@ -9,7 +9,9 @@ use std::{
     },
 };
 
-use super::Cache;
+use crate::utils::hash::{BasicHasher, Hasher64};
+
+use super::{Cache, EvictCallback, Pinned};
 
 #[derive(Clone, Copy)]
 struct KeyRef<K> {
@@ -63,6 +65,12 @@ impl<K> Eq for KeyRef<K> where K: Eq {}
 
 struct LruEntry<K, V> {
     charge: usize,
+    // a pinned entry is never evicted, and once erased or replaced it is only
+    // detached from the cache, its last unpin drops it
+    pins: usize,
+    detached: bool,
+    // detached by erase, the evict callback sees it on the last unpin
+    erased: bool,
     key: MaybeUninit<K>,
     value: MaybeUninit<V>,
     prev: *mut Self,
@@ -73,6 +81,9 @@ impl<K, V> Default for LruEntry<K, V> {
     fn default() -> Self {
         Self {
             charge: 0,
+            pins: 0,
+            detached: false,
+            erased: false,
             key: MaybeUninit::uninit(),
             value: MaybeUninit::uninit(),
             prev: std::ptr::null_mut(),
@@ -85,6 +96,9 @@ impl<K, V> LruEntry<K, V> {
     fn new(key: K, value: V, charge: usize) -> Self {
         Self {
             charge,
+            pins: 0,
+            detached: false,
+            erased: false,
             key: MaybeUninit::new(key),
             value: MaybeUninit::new(value),
             prev: std::ptr::null_mut(),
@@ -133,6 +147,7 @@ pub struct LruCache<K, V> {
     inner: Arc<Mutex<LruCacheInner<K, V>>>,
     usage: Arc<AtomicUsize>,
     cap: usize,
+    on_evict: Option<EvictCallback<K, V>>,
 }
 
 unsafe impl<K: Send, V: Send> Send for LruCache<K, V> {}
@@ -143,6 +158,14 @@ where
     K: Hash + Eq,
 {
     pub fn new(cap: usize) -> Self {
+        Self::new_with(cap, None)
+    }
+
+    pub fn with_evict_callback(cap: usize, on_evict: EvictCallback<K, V>) -> Self {
+        Self::new_with(cap, Some(on_evict))
+    }
+
+    fn new_with(cap: usize, on_evict: Option<EvictCallback<K, V>>) -> Self {
         unsafe {
             let head = Box::into_raw(Box::new(LruEntry::default()));
             let tail = Box::into_raw(Box::new(LruEntry::default()));
@@ -159,6 +182,31 @@ where
                 inner: Arc::new(Mutex::new(inner)),
                 usage: Arc::new(AtomicUsize::new(0)),
                 cap,
+                on_evict,
+            }
+        }
+    }
+
+    // takes a pinned node, already removed from the table, out of the cache
+    fn detach(&self, inner: &mut LruCacheInner<K, V>, mut node: Box<LruEntry<K, V>>, erased: bool) {
+        inner.remove_node(node.as_mut());
+        self.usage.fetch_sub(node.charge, Ordering::Relaxed);
+        node.detached = true;
+        node.erased = erased;
+        let _ = Box::into_raw(node);
+    }
+
+    fn unpin(&self, node: *mut LruEntry<K, V>) {
+        let _inner = self.inner.lock().unwrap();
+        unsafe {
+            (*node).pins -= 1;
+            if (*node).pins == 0 && (*node).detached {
+                let mut node = Box::from_raw(node);
+                if let (true, Some(on_evict)) = (node.erased, &self.on_evict) {
+                    on_evict(&*node.key.as_ptr(), &*node.value.as_ptr());
+                }
+                node.key.assume_init_drop();
+                node.value.assume_init_drop();
             }
         }
     }
@@ -175,7 +223,14 @@ where
         }
 
         let mut inner = self.inner.lock().unwrap();
-        let old_value = match inner.table.get_mut(&KeyRef::from(&key)) {
+        // the value of a pinned entry is still referenced, so it is not handed back
+        let key_ref = KeyRef::from(&key);
+        if matches!(inner.table.get(&key_ref), Some(node) if node.pins > 0) {
+            let node = inner.table.remove(&key_ref).unwrap();
+            self.detach(&mut inner, node, false);
+        }
+
+        let (inserted, old_value) = match inner.table.get_mut(&key_ref) {
             Some(node) => {
                 unsafe {
                     std::mem::swap(&mut value, &mut *node.as_mut().value.as_mut_ptr());
@@ -186,7 +241,7 @@ where
 
                 let ptr = node.as_mut() as *mut _;
                 inner.update_node(ptr);
-                Some(value)
+                (ptr, Some(value))
             }
             None => {
                 let mut node = Box::new(LruEntry::new(key, value, charge));
@@ -195,20 +250,29 @@ where
                 let key_ref = KeyRef::from(unsafe { &*node.key.as_ptr() });
                 inner.table.insert(key_ref, node);
                 self.usage.fetch_add(charge, Ordering::Relaxed);
-                None
+                (ptr, None)
             }
         };
 
+        // with everything else pinned the cache stays over capacity rather than
+        // dropping the entry just inserted
         unsafe {
-            while self.total_charge() > self.cap && (*inner.head).next != inner.tail {
-                let node = (*inner.tail).prev;
-                inner.remove_node(node);
-                let k = KeyRef::from(&(*(*node).key.as_ptr()));
-                let node_box = inner.table.remove(&k);
-                self.usage.fetch_sub((*node).charge, Ordering::Relaxed);
-                (*node).key.assume_init_drop();
-                (*node).value.assume_init_drop();
-                drop(node_box);
+            let mut node = (*inner.tail).prev;
+            while self.total_charge() > self.cap && node != inner.head {
+                let prev = (*node).prev;
+                if (*node).pins == 0 && node != inserted {
+                    inner.remove_node(node);
+                    let k = KeyRef::from(&(*(*node).key.as_ptr()));
+                    let node_box = inner.table.remove(&k);
+                    self.usage.fetch_sub((*node).charge, Ordering::Relaxed);
+                    if let Some(on_evict) = &self.on_evict {
+                        on_evict(&*(*node).key.as_ptr(), &*(*node).value.as_ptr());
+                    }
+                    (*node).key.assume_init_drop();
+                    (*node).value.assume_init_drop();
+                    drop(node_box);
+                }
+                node = prev;
             }
         }
 
@@ -227,10 +291,33 @@ where
         }
     }
 
+    fn get_pinned(&self, key: &K) -> Option<Pinned<'_, V>> {
+        let key_ref = KeyRef::from(key);
+        let mut inner = self.inner.lock().unwrap();
+        let node = inner.table.get_mut(&key_ref)?.as_mut() as *mut LruEntry<K, V>;
+        inner.update_node(node);
+        unsafe {
+            (*node).pins += 1;
+            Some(Pinned::new(&*(*node).value.as_ptr(), move || {
+                self.unpin(node)
+            }))
+        }
+    }
+
     fn erase(&self, key: &K) -> Option<V> {
         let k = KeyRef::from(key);
         let mut inner = self.inner.lock().unwrap();
         if let Some(mut node) = inner.table.remove(&k) {
+            if node.pins > 0 {
+                self.detach(&mut inner, node, true);
+                return None;
+            }
+            unsafe {
+                if let Some(on_evict) = &self.on_evict {
+                    on_evict(&*node.key.as_ptr(), &*node.value.as_ptr());
+                }
+            }
+
             self.usage.fetch_sub(node.charge, Ordering::Relaxed);
             inner.remove_node(node.as_mut());
             unsafe {
@@ -262,23 +349,76 @@ impl<K, V> Drop for LruCacheInner<K, V> {
 
 pub struct SharededLruCache<K, V> {
     caches: Arc<Vec<LruCache<K, V>>>,
+    hasher: Arc<dyn Hasher64>,
+}
+
+const KEY_CHUNK_LEN: usize = 64;
+
+// feeds the bytes a key hashes into to a `Hasher64` through a fixed buffer. A key
+// of at most `KEY_CHUNK_LEN` bytes hashes as `hash64` of its bytes, a longer one
+// folds the hash of every full chunk into the next.
+struct KeyHasher<'a> {
+    hasher: &'a dyn Hasher64,
+    buf: [u8; KEY_CHUNK_LEN],
+    len: usize,
+    state: Option<u64>,
+}
+
+impl<'a> KeyHasher<'a> {
+    fn new(hasher: &'a dyn Hasher64) -> Self {
+        Self {
+            hasher,
+            buf: [0; KEY_CHUNK_LEN],
+            len: 0,
+            state: None,
+        }
+    }
+
+    fn fold(state: Option<u64>, hash: u64) -> u64 {
+        match state {
+            Some(state) => (state.rotate_left(5) ^ hash).wrapping_mul(0x9e37_79b9_7f4a_7c15),
+            None => hash,
+        }
+    }
+}
+
+impl Hasher for KeyHasher<'_> {
+    fn finish(&self) -> u64 {
+        Self::fold(self.state, self.hasher.hash64(&self.buf[..self.len]))
+    }
+
+    fn write(&mut self, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            if self.len == KEY_CHUNK_LEN {
+                let hash = self.hasher.hash64(&self.buf);
+                self.state = Some(Self::fold(self.state, hash));
+                self.len = 0;
+            }
+            let n = bytes.len().min(KEY_CHUNK_LEN - self.len);
+            self.buf[self.len..self.len + n].copy_from_slice(&bytes[..n]);
+            self.len += n;
+            bytes = &bytes[n..];
+        }
+    }
 }
 
 impl<K, V> SharededLruCache<K, V>
 where
     K: Send + Sync + Hash + Eq,
     V: Send + Sync,
-    // H: Hasher + Default,
 {
     fn shared_hash(&self, hash: usize) -> usize {
         hash % self.caches.len()
     }
 
-    fn get_cache(&self, key: &K) -> &LruCache<K, V> {
-        let mut hasher = DefaultHasher::default();
+    fn shard_of(&self, key: &K) -> usize {
+        let mut hasher = KeyHasher::new(self.hasher.as_ref());
         key.hash(&mut hasher);
-        let index = self.shared_hash(hasher.finish() as usize);
-        &self.caches[index]
+        self.shared_hash(hasher.finish() as usize)
+    }
+
+    fn get_cache(&self, key: &K) -> &LruCache<K, V> {
+        &self.caches[self.shard_of(key)]
     }
 
     pub fn new(len: usize, per_cap: usize) -> Self {
@@ -288,15 +428,32 @@ where
         }
         Self {
             caches: Arc::new(caches),
+            hasher: Arc::new(BasicHasher),
         }
     }
+
+    pub fn with_evict_callback(len: usize, per_cap: usize, on_evict: EvictCallback<K, V>) -> Self {
+        let mut caches = Vec::with_capacity(len);
+        for _ in 0..len {
+            caches.push(LruCache::with_evict_callback(per_cap, on_evict.clone()));
+        }
+        Self {
+            caches: Arc::new(caches),
+            hasher: Arc::new(BasicHasher),
+        }
+    }
+
+    /// Pick shards with `hasher` instead of the default `BasicHasher`.
+    pub fn with_hasher(mut self, hasher: Arc<dyn Hasher64>) -> Self {
+        self.hasher = hasher;
+        self
+    }
 }
 
 impl<K, V> Cache<K, V> for SharededLruCache<K, V>
 where
     K: Send + Sync + Hash + Eq,
     V: Send + Sync,
-    // H: Hasher + Default + Send + Sync,
 {
     fn insert(&self, key: K, value: V, charge: usize) -> Option<V> {
         self.get_cache(&key).insert(key, value, charge)
@@ -306,6 +463,10 @@ where
         self.get_cache(key).get(key)
     }
 
+    fn get_pinned(&self, key: &K) -> Option<Pinned<'_, V>> {
+        self.get_cache(key).get_pinned(key)
+    }
+
     fn erase(&self, key: &K) -> Option<V> {
         self.get_cache(key).erase(key)
     }
@@ -317,7 +478,18 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::caches::{lru::LruCache, Cache};
+    use std::{
+        hash::Hasher,
+        sync::{Arc, Mutex},
+    };
+
+    use crate::utils::{
+        caches::{
+            lru::{KeyHasher, LruCache, SharededLruCache},
+            Cache,
+        },
+        hash::Hasher64,
+    };
 
     #[test]
     fn empty_cache() {
@@ -366,4 +538,173 @@ mod tests {
             assert_eq!(res, Some(&i));
         }
     }
+
+    #[test]
+    fn evict_callback() {
+        let evicted = Arc::new(Mutex::new(vec![]));
+        let cb_evicted = evicted.clone();
+        let cache = LruCache::with_evict_callback(
+            3,
+            Arc::new(move |k: &i32, v: &i32| {
+                assert_eq!(k, v);
+                cb_evicted.lock().unwrap().push(*k);
+            }),
+        );
+
+        for i in 0..5 {
+            cache.insert(i, i, 1);
+        }
+        assert_eq!(*evicted.lock().unwrap(), vec![0, 1]);
+
+        // replacing a value is not an eviction
+        assert_eq!(cache.insert(4, 4, 1), Some(4));
+        assert_eq!(cache.erase(&3), Some(3));
+        assert_eq!(*evicted.lock().unwrap(), vec![0, 1, 3]);
+
+        drop(cache);
+        assert_eq!(*evicted.lock().unwrap(), vec![0, 1, 3]);
+    }
+
+    #[test]
+    fn pinned_entry_survives_eviction() {
+        let cache = LruCache::new(10);
+        cache.insert(-1, -1, 1);
+        let pinned = cache.get_pinned(&-1).unwrap();
+        for i in 0..100 {
+            cache.insert(i, i, 1);
+        }
+        assert_eq!(*pinned, -1);
+        // the pinned entry keeps its charge, the rest of the cache makes room
+        assert_eq!(cache.total_charge(), 10);
+
+        drop(pinned);
+        cache.insert(100, 100, 1);
+        assert_eq!(cache.get(&-1), None);
+        assert_eq!(cache.total_charge(), 10);
+    }
+
+    #[test]
+    fn pinned_entry_over_capacity() {
+        let cache = LruCache::new(2);
+        cache.insert(0, 0, 1);
+        cache.insert(1, 1, 1);
+        let p0 = cache.get_pinned(&0).unwrap();
+        let p1 = cache.get_pinned(&1).unwrap();
+        cache.insert(2, 2, 1);
+        assert_eq!(cache.total_charge(), 3);
+        assert_eq!((*p0, *p1), (0, 1));
+        assert_eq!(cache.get(&2), Some(&2));
+    }
+
+    #[test]
+    fn erase_pinned_entry() {
+        let evicted = Arc::new(Mutex::new(vec![]));
+        let cb_evicted = evicted.clone();
+        let cache = LruCache::with_evict_callback(
+            10,
+            Arc::new(move |k: &i32, _: &String| cb_evicted.lock().unwrap().push(*k)),
+        );
+        cache.insert(0, "zero".to_string(), 1);
+        cache.insert(1, "one".to_string(), 1);
+
+        let zero = cache.get_pinned(&0).unwrap();
+        let zero_again = cache.get_pinned(&0).unwrap();
+        assert_eq!(cache.erase(&0), None);
+        assert_eq!(cache.get(&0), None);
+        assert_eq!(cache.total_charge(), 1);
+        // the callback waits for the last guard
+        drop(zero);
+        assert!(evicted.lock().unwrap().is_empty());
+        assert_eq!(*zero_again, "zero");
+        drop(zero_again);
+        assert_eq!(*evicted.lock().unwrap(), vec![0]);
+
+        // replacing a pinned value leaves the guard on the old one
+        let one = cache.get_pinned(&1).unwrap();
+        assert_eq!(cache.insert(1, "uno".to_string(), 1), None);
+        assert_eq!(*one, "one");
+        assert_eq!(cache.get(&1).map(|v| v.as_str()), Some("uno"));
+        assert_eq!(cache.total_charge(), 1);
+        drop(one);
+        assert_eq!(*evicted.lock().unwrap(), vec![0]);
+    }
+
+    #[test]
+    fn sharded_pinned_entry() {
+        let cache = SharededLruCache::new(4, 4);
+        cache.insert(0, 0, 1);
+        let pinned = cache.get_pinned(&0).unwrap();
+        for i in 1..100 {
+            cache.insert(i, i, 1);
+        }
+        assert_eq!(*pinned, 0);
+        assert!(cache.get_pinned(&1000).is_none());
+    }
+
+    struct Fnv1a;
+
+    impl Hasher64 for Fnv1a {
+        fn hash64(&self, data: &[u8]) -> u64 {
+            data.iter().fold(0xcbf29ce484222325, |h, b| {
+                (h ^ *b as u64).wrapping_mul(0x100000001b3)
+            })
+        }
+    }
+
+    #[test]
+    fn sharded_hasher() {
+        const SHARDS: usize = 16;
+        const KEYS: usize = 16000;
+
+        let keys: Vec<u64> = (0..KEYS).map(|_| rand::random()).collect();
+        let basic: SharededLruCache<u64, u64> = SharededLruCache::new(SHARDS, KEYS);
+        let fnv: SharededLruCache<u64, u64> =
+            SharededLruCache::new(SHARDS, KEYS).with_hasher(Arc::new(Fnv1a));
+
+        let moved = keys
+            .iter()
+            .filter(|k| basic.shard_of(k) != fnv.shard_of(k))
+            .count();
+        assert!(moved > KEYS / 2, "moved: {}", moved);
+
+        // every shard holds the expected count within 20%
+        for cache in [&basic, &fnv] {
+            let mut counts = [0usize; SHARDS];
+            for key in keys.iter() {
+                counts[cache.shard_of(key)] += 1;
+                cache.insert(*key, *key, 1);
+            }
+            let expected = KEYS / SHARDS;
+            for count in counts {
+                assert!(count.abs_diff(expected) < expected / 5, "{:?}", counts);
+            }
+            for key in keys.iter() {
+                assert_eq!(cache.get(key), Some(key));
+            }
+        }
+    }
+
+    #[test]
+    fn key_hasher_chunks() {
+        let hash = |bytes: &[&[u8]]| {
+            let mut hasher = KeyHasher::new(&Fnv1a);
+            for b in bytes {
+                hasher.write(b);
+            }
+            hasher.finish()
+        };
+
+        // a short key hashes as its bytes
+        assert_eq!(hash(&[b"abc", b"def"]), Fnv1a.hash64(b"abcdef"));
+
+        // a long key hashes the same however it is written
+        let long: Vec<u8> = (0..200).collect();
+        let whole = hash(&[&long]);
+        assert_eq!(hash(&[&long[..1], &long[1..100], &long[100..]]), whole);
+        assert_eq!(hash(&[&long[..64], &long[64..128], &long[128..]]), whole);
+
+        let mut other = long.clone();
+        other[199] ^= 1;
+        assert_ne!(hash(&[&other]), whole);
+    }
 }
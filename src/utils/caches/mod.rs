@@ -1,5 +1,11 @@
+use std::{ops::Deref, sync::Arc};
+
 pub mod lru;
 
+/// Called with each entry a cache evicts or erases, while the cache is locked,
+/// so it must not call back into the cache.
+pub type EvictCallback<K, V> = Arc<dyn Fn(&K, &V) + Send + Sync>;
+
 pub trait Cache<K, V>: Sync + Send
 where
     K: Sync + Send,
@@ -9,7 +15,45 @@ where
 
     fn get(&self, key: &K) -> Option<&V>;
 
+    /// Like `get`, but the entry is not evicted until the guard is dropped.
+    fn get_pinned(&self, key: &K) -> Option<Pinned<'_, V>>;
+
+    /// Remove `key` and hand back its value. A pinned entry is still referenced, so
+    /// it is only taken out of the cache: `erase` returns `None`, and the evict
+    /// callback sees the entry when its last guard is dropped rather than now.
     fn erase(&self, key: &K) -> Option<V>;
 
     fn total_charge(&self) -> usize;
 }
+
+/// A cached value that stays alive until the guard is dropped. Pinned entries are
+/// skipped by eviction, so the cache may go over its capacity while they are held.
+pub struct Pinned<'a, V> {
+    value: &'a V,
+    unpin: Option<Box<dyn FnOnce() + 'a>>,
+}
+
+impl<'a, V> Pinned<'a, V> {
+    pub(crate) fn new(value: &'a V, unpin: impl FnOnce() + 'a) -> Self {
+        Self {
+            value,
+            unpin: Some(Box::new(unpin)),
+        }
+    }
+}
+
+impl<V> Deref for Pinned<'_, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        self.value
+    }
+}
+
+impl<V> Drop for Pinned<'_, V> {
+    fn drop(&mut self) {
+        if let Some(unpin) = self.unpin.take() {
+            unpin();
+        }
+    }
+}
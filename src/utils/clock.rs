@@ -0,0 +1,84 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Source of time for everything that acts on elapsed time, e.g. interval syncs.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Seconds since the unix epoch.
+    fn now_unix(&self) -> u64;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn now_unix(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs())
+    }
+}
+
+/// A clock that only moves when advanced, for deterministic tests.
+#[derive(Debug)]
+pub struct MockClock {
+    start: Instant,
+    start_unix: u64,
+    elapsed: Mutex<Duration>,
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            start_unix: SystemClock.now_unix(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, d: Duration) {
+        *self.elapsed.lock().unwrap() += d;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + *self.elapsed.lock().unwrap()
+    }
+
+    fn now_unix(&self) -> u64 {
+        self.start_unix + self.elapsed.lock().unwrap().as_secs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Clock, MockClock};
+
+    #[test]
+    fn mock_clock_moves_when_advanced() {
+        let clock = MockClock::new();
+        let (now, unix) = (clock.now(), clock.now_unix());
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(clock.now(), now);
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(clock.now() - now, Duration::from_secs(90));
+        assert_eq!(clock.now_unix() - unix, 90);
+    }
+}
@@ -1,4 +1,8 @@
-use crate::comparator::Comparator;
+use crate::{
+    comparator::Comparator,
+    error::{Corruption, CorruptionKind, DBError, DBResult},
+    utils::{encoding, varint::VarInt},
+};
 
 #[derive(Debug, Clone, Copy)]
 pub struct BytewiseComparator;
@@ -45,3 +49,376 @@ impl Comparator for BytewiseComparator {
         key.to_vec()
     }
 }
+
+// | user key | seq << 8 | value type, 8b |
+const INTERNAL_KEY_TAIL_LEN: usize = std::mem::size_of::<u64>();
+
+/// The largest seq a tail holds, it keeps the top 56 bits.
+pub const MAX_SEQUENCE: u64 = (1 << 56) - 1;
+
+/// What the entry of an internal key holds, the low byte of its tail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ValueType {
+    Deletion = 0,
+    Value = 1,
+}
+
+impl TryFrom<u8> for ValueType {
+    type Error = DBError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(ValueType::Deletion),
+            1 => Ok(ValueType::Value),
+            _ => Err(Corruption::new(
+                CorruptionKind::BadType,
+                format!("invalid value type, found number: {}", value),
+            )
+            .into()),
+        }
+    }
+}
+
+pub fn pack_tail(seq: u64, ty: ValueType) -> u64 {
+    assert!(seq <= MAX_SEQUENCE, "sequence {} too large", seq);
+    seq << 8 | ty as u64
+}
+
+/// Split a tail into its seq and value type.
+pub fn unpack_tail(tail: u64) -> DBResult<(u64, ValueType)> {
+    Ok((tail >> 8, ValueType::try_from(tail as u8)?))
+}
+
+// the largest tail, it sorts before every other tail of the same user key
+const MAX_TAIL: u64 = u64::MAX;
+
+/// How the `seq << 8 | value type` tail of an internal key is stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyTailEncoding {
+    /// 8 bytes little-endian, the user key is everything but the last 8 bytes.
+    #[default]
+    Fixed,
+    /// The seq as a varint with its bytes reversed, then the type byte. A seq below
+    /// 128 takes 2 bytes instead of 8, and the largest take 9. The user key is only
+    /// found by decoding the varint backwards from the type byte, so it can't be
+    /// sliced off by a fixed length.
+    Varint,
+}
+
+impl KeyTailEncoding {
+    pub fn put_tail(&self, key: &mut Vec<u8>, tail: u64) {
+        match self {
+            Self::Fixed => encoding::put_u64(key, tail),
+            Self::Varint => {
+                let start = key.len();
+                (tail >> 8).put_varint(key);
+                key[start..].reverse();
+                key.push(tail as u8);
+            }
+        }
+    }
+
+    /// Split an internal key into its user key and `seq << 8 | value type` tail.
+    pub fn split<'a>(&self, key: &'a [u8]) -> DBResult<(&'a [u8], u64)> {
+        let truncated = || {
+            Corruption::new(
+                CorruptionKind::Truncated,
+                format!("internal key tail truncated: {} bytes", key.len()),
+            )
+        };
+        match self {
+            Self::Fixed => {
+                if key.len() < INTERNAL_KEY_TAIL_LEN {
+                    return Err(truncated().into());
+                }
+                let (user_key, tail) = key.split_at(key.len() - INTERNAL_KEY_TAIL_LEN);
+                Ok((user_key, encoding::get_u64(tail)))
+            }
+            Self::Varint => {
+                let (&ty, mut rest) = key.split_last().ok_or_else(truncated)?;
+                let mut seq = 0u64;
+                for shift in (0..64).step_by(7) {
+                    let (&byte, front) = rest.split_last().ok_or_else(truncated)?;
+                    rest = front;
+                    seq |= ((byte & 0x7f) as u64) << shift;
+                    if byte & 0x80 == 0 {
+                        return Ok((rest, seq << 8 | ty as u64));
+                    }
+                }
+                Err(Corruption::new(CorruptionKind::BadType, "internal key seq overflows").into())
+            }
+        }
+    }
+}
+
+/// Orders internal keys, a user key followed by a `seq << 8 | value type` tail stored
+/// as `KeyTailEncoding` says. User keys ascend by the wrapped comparator, equal user
+/// keys put the newest seq first.
+#[derive(Debug, Clone, Copy)]
+pub struct InternalKeyComparator<C: Comparator> {
+    user_comparator: C,
+    tail_encoding: KeyTailEncoding,
+}
+
+impl<C: Comparator> InternalKeyComparator<C> {
+    pub fn new(user_comparator: C) -> Self {
+        Self::with_tail_encoding(user_comparator, KeyTailEncoding::Fixed)
+    }
+
+    pub fn with_tail_encoding(user_comparator: C, tail_encoding: KeyTailEncoding) -> Self {
+        Self {
+            user_comparator,
+            tail_encoding,
+        }
+    }
+
+    pub fn user_comparator(&self) -> &C {
+        &self.user_comparator
+    }
+
+    pub fn tail_encoding(&self) -> KeyTailEncoding {
+        self.tail_encoding
+    }
+
+    /// Split an internal key into its user key, seq and value type.
+    pub fn parse<'a>(&self, key: &'a [u8]) -> DBResult<(&'a [u8], u64, ValueType)> {
+        let (user_key, tail) = self.split(key)?;
+        let (seq, ty) = unpack_tail(tail)?;
+        Ok((user_key, seq, ty))
+    }
+
+    fn split<'a>(&self, key: &'a [u8]) -> DBResult<(&'a [u8], u64)> {
+        self.tail_encoding.split(key)
+    }
+}
+
+impl<C: Comparator> Comparator for InternalKeyComparator<C> {
+    // a corrupted key that does not split is ordered by its raw bytes, so a seek
+    // over it goes on and the block reader reports the corruption
+    fn compare(&self, a: &[u8], b: &[u8]) -> std::cmp::Ordering {
+        match (self.split(a), self.split(b)) {
+            (Ok((user_a, tail_a)), Ok((user_b, tail_b))) => self
+                .user_comparator
+                .compare(user_a, user_b)
+                .then_with(|| tail_b.cmp(&tail_a)),
+            _ => a.cmp(b),
+        }
+    }
+
+    // keys of one tail encoding can't be read with the other
+    fn name(&self) -> &str {
+        match self.tail_encoding {
+            KeyTailEncoding::Fixed => "arisdb.InternalKeyComparator",
+            KeyTailEncoding::Varint => "arisdb.InternalKeyComparator.VarintTail",
+        }
+    }
+
+    // shorten the user key, the largest tail makes it the first internal key of that user key
+    fn find_shortest_separator(&self, start: &[u8], limit: &[u8]) -> Vec<u8> {
+        let (Ok((user_start, _)), Ok((user_limit, _))) = (self.split(start), self.split(limit))
+        else {
+            return start.to_vec();
+        };
+        let mut res = self
+            .user_comparator
+            .find_shortest_separator(user_start, user_limit);
+        if res.len() < user_start.len() && self.user_comparator.compare(user_start, &res).is_lt() {
+            self.tail_encoding.put_tail(&mut res, MAX_TAIL);
+            return res;
+        }
+        start.to_vec()
+    }
+
+    fn find_short_successor(&self, key: &[u8]) -> Vec<u8> {
+        let Ok((user_key, _)) = self.split(key) else {
+            return key.to_vec();
+        };
+        let mut res = self.user_comparator.find_short_successor(user_key);
+        if res.len() < user_key.len() && self.user_comparator.compare(user_key, &res).is_lt() {
+            self.tail_encoding.put_tail(&mut res, MAX_TAIL);
+            return res;
+        }
+        key.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        comparator::Comparator,
+        error::{Corruption, CorruptionKind, DBError, DBResult},
+        utils::encoding,
+    };
+
+    use super::{pack_tail, BytewiseComparator, InternalKeyComparator, KeyTailEncoding, ValueType};
+
+    fn ikey(user_key: &[u8], seq: u64, ty: u8) -> Vec<u8> {
+        let mut key = user_key.to_vec();
+        encoding::put_u64(&mut key, seq << 8 | ty as u64);
+        key
+    }
+
+    #[test]
+    fn newest_seq_first() {
+        let comp = InternalKeyComparator::new(BytewiseComparator);
+        let (new, old) = (ikey(b"foo", 100, 1), ikey(b"foo", 99, 1));
+        assert!(comp.compare(&new, &old).is_lt());
+        assert!(comp.compare(&old, &new).is_gt());
+        assert!(comp.compare(&new, &new).is_eq());
+
+        // the user key decides first
+        assert!(comp.compare(&ikey(b"bar", 1, 1), &new).is_lt());
+        assert!(comp.compare(&ikey(b"", 1, 1), &ikey(b"a", 1, 1)).is_lt());
+    }
+
+    #[test]
+    fn shortest_separator() {
+        let comp = InternalKeyComparator::new(BytewiseComparator);
+        let start = ikey(b"foo", 100, 1);
+        let limit = ikey(b"hello", 200, 1);
+        let sep = comp.find_shortest_separator(&start, &limit);
+        assert_eq!(sep, ikey(b"g", u64::MAX >> 8, 0xff));
+        assert!(comp.compare(&start, &sep).is_lt());
+        assert!(comp.compare(&sep, &limit).is_lt());
+
+        // same user key or a prefix can't be shortened
+        let limit = ikey(b"foo", 99, 1);
+        assert_eq!(comp.find_shortest_separator(&start, &limit), start);
+        let limit = ikey(b"foobar", 200, 1);
+        assert_eq!(comp.find_shortest_separator(&start, &limit), start);
+    }
+
+    #[test]
+    fn short_successor() {
+        let comp = InternalKeyComparator::new(BytewiseComparator);
+        let key = ikey(b"foo", 100, 1);
+        let succ = comp.find_short_successor(&key);
+        assert_eq!(succ, ikey(b"g", u64::MAX >> 8, 0xff));
+        assert!(comp.compare(&key, &succ).is_lt());
+
+        let key = ikey(b"\xff\xff", 100, 1);
+        assert_eq!(comp.find_short_successor(&key), key);
+    }
+
+    fn varint_ikey(user_key: &[u8], seq: u64, ty: u8) -> Vec<u8> {
+        let mut key = user_key.to_vec();
+        KeyTailEncoding::Varint.put_tail(&mut key, seq << 8 | ty as u64);
+        key
+    }
+
+    #[test]
+    fn tail_round_trip() {
+        let seqs = [
+            0,
+            1,
+            127,
+            128,
+            300,
+            1 << 32,
+            (u64::MAX >> 8) - 1,
+            u64::MAX >> 8,
+        ];
+        for encoding in [KeyTailEncoding::Fixed, KeyTailEncoding::Varint] {
+            for user_key in [b"".as_slice(), b"foo", b"\xff\x80\x80"] {
+                for seq in seqs {
+                    let mut key = user_key.to_vec();
+                    encoding.put_tail(&mut key, seq << 8 | 1);
+                    assert_eq!(encoding.split(&key).unwrap(), (user_key, seq << 8 | 1));
+                }
+            }
+        }
+
+        assert_eq!(varint_ikey(b"foo", 1, 1).len(), 3 + 2);
+        assert_eq!(varint_ikey(b"foo", 128, 1).len(), 3 + 3);
+        assert_eq!(varint_ikey(b"foo", u64::MAX >> 8, 1).len(), 3 + 9);
+        assert_eq!(ikey(b"foo", 1, 1).len(), 3 + 8);
+    }
+
+    #[test]
+    fn varint_tail_order() {
+        let comp =
+            InternalKeyComparator::with_tail_encoding(BytewiseComparator, KeyTailEncoding::Varint);
+        let (new, old) = (varint_ikey(b"foo", 300, 1), varint_ikey(b"foo", 2, 1));
+        assert!(comp.compare(&new, &old).is_lt());
+        assert!(comp.compare(&varint_ikey(b"foo", 2, 2), &old).is_lt());
+        assert!(comp.compare(&varint_ikey(b"bar", 1, 1), &new).is_lt());
+        assert!(comp.compare(&varint_ikey(b"fo", 1, 1), &new).is_lt());
+        assert_ne!(
+            comp.name(),
+            InternalKeyComparator::new(BytewiseComparator).name()
+        );
+
+        let limit = varint_ikey(b"hello", 200, 1);
+        let sep = comp.find_shortest_separator(&new, &limit);
+        assert_eq!(sep, varint_ikey(b"g", u64::MAX >> 8, 0xff));
+        assert!(comp.compare(&new, &sep).is_lt());
+        assert!(comp.compare(&sep, &limit).is_lt());
+        let succ = comp.find_short_successor(&new);
+        assert_eq!(succ, varint_ikey(b"g", u64::MAX >> 8, 0xff));
+    }
+
+    #[test]
+    fn parse_value_type() {
+        assert_eq!(ValueType::try_from(0).unwrap(), ValueType::Deletion);
+        assert_eq!(ValueType::try_from(1).unwrap(), ValueType::Value);
+        assert!(matches!(
+            ValueType::try_from(2),
+            Err(DBError::Corruption(Corruption {
+                kind: CorruptionKind::BadType,
+                ..
+            }))
+        ));
+
+        for encoding in [KeyTailEncoding::Fixed, KeyTailEncoding::Varint] {
+            let comp = InternalKeyComparator::with_tail_encoding(BytewiseComparator, encoding);
+            let mut key = b"foo".to_vec();
+            encoding.put_tail(&mut key, pack_tail(300, ValueType::Deletion));
+            assert_eq!(
+                comp.parse(&key).unwrap(),
+                (b"foo".as_slice(), 300, ValueType::Deletion)
+            );
+
+            let mut key = b"foo".to_vec();
+            encoding.put_tail(&mut key, 300 << 8 | 7);
+            assert!(comp.parse(&key).is_err());
+        }
+    }
+
+    fn corruption_kind(res: DBResult<(&[u8], u64)>) -> CorruptionKind {
+        match res {
+            Err(DBError::Corruption(c)) => c.kind,
+            res => panic!("expected corruption: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn malformed_tail() {
+        assert_eq!(
+            corruption_kind(KeyTailEncoding::Fixed.split(b"foo")),
+            CorruptionKind::Truncated
+        );
+        assert_eq!(
+            corruption_kind(KeyTailEncoding::Varint.split(b"")),
+            CorruptionKind::Truncated
+        );
+        // every seq byte has its continuation bit set
+        assert_eq!(
+            corruption_kind(KeyTailEncoding::Varint.split(b"\x80\x81\x01")),
+            CorruptionKind::Truncated
+        );
+        assert_eq!(
+            corruption_kind(KeyTailEncoding::Varint.split(&[0x80; 12])),
+            CorruptionKind::BadType
+        );
+
+        // the comparator falls back to the raw bytes and does not panic
+        let comp = InternalKeyComparator::new(BytewiseComparator);
+        let key = ikey(b"foo", 1, 1);
+        assert!(comp.compare(b"foo", &key).is_lt());
+        assert!(comp.compare(&key, b"foo").is_gt());
+        assert_eq!(comp.find_shortest_separator(b"a", &key), b"a");
+        assert_eq!(comp.find_short_successor(b"a"), b"a");
+        assert!(comp.parse(b"foo").is_err());
+    }
+}
@@ -0,0 +1,76 @@
+// Fixed width integers of every on-disk format are little-endian: redo log record
+// headers and crc, block restart points, filter block offsets, block trailer crc and
+// the table footer magic number. Formats encode them through these helpers rather
+// than the `bytes` methods, so none can pick the other byte order by accident.
+//
+// The `get_*` helpers read from the front of the slice and panic if it is too short.
+
+use bytes::{Buf, BufMut};
+
+pub fn put_u16(buf: &mut impl BufMut, n: u16) {
+    buf.put_u16_le(n);
+}
+
+pub fn put_u32(buf: &mut impl BufMut, n: u32) {
+    buf.put_u32_le(n);
+}
+
+pub fn put_u64(buf: &mut impl BufMut, n: u64) {
+    buf.put_u64_le(n);
+}
+
+pub fn get_u16(mut buf: &[u8]) -> u16 {
+    buf.get_u16_le()
+}
+
+pub fn get_u32(mut buf: &[u8]) -> u32 {
+    buf.get_u32_le()
+}
+
+pub fn get_u64(mut buf: &[u8]) -> u64 {
+    buf.get_u64_le()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{get_u16, get_u32, get_u64, put_u16, put_u32, put_u64};
+
+    #[test]
+    fn fixed_width_is_little_endian() {
+        let mut buf = Vec::new();
+        put_u16(&mut buf, 0x0102);
+        put_u32(&mut buf, 0x0102_0304);
+        put_u64(&mut buf, 0x0102_0304_0506_0708);
+        assert_eq!(
+            buf,
+            [
+                0x02, 0x01, //
+                0x04, 0x03, 0x02, 0x01, //
+                0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01,
+            ]
+        );
+
+        assert_eq!(get_u16(&buf), 0x0102);
+        assert_eq!(get_u32(&buf[2..]), 0x0102_0304);
+        assert_eq!(get_u64(&buf[6..]), 0x0102_0304_0506_0708);
+    }
+
+    #[test]
+    fn round_trip_bounds() {
+        for n in [0, 1, u16::MAX - 1, u16::MAX] {
+            let mut buf = Vec::new();
+            put_u16(&mut buf, n);
+            assert_eq!(get_u16(&buf), n);
+        }
+        for n in [0, 1, u32::MAX - 1, u32::MAX] {
+            let mut buf = Vec::new();
+            put_u32(&mut buf, n);
+            assert_eq!(get_u32(&buf), n);
+        }
+        for n in [0, 1, u64::MAX - 1, u64::MAX] {
+            let mut buf = Vec::new();
+            put_u64(&mut buf, n);
+            assert_eq!(get_u64(&buf), n);
+        }
+    }
+}
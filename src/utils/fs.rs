@@ -0,0 +1,80 @@
+use crate::io::AccessPattern;
+
+/// Reserve `len` bytes of disk for `fd` without changing its size, so later writes land
+/// in allocated extents. Returns false where preallocation is not supported.
+#[cfg(target_os = "linux")]
+pub fn preallocate(fd: &std::fs::File, len: u64) -> std::io::Result<bool> {
+    use std::os::fd::AsRawFd;
+
+    let res = unsafe {
+        libc::fallocate(
+            fd.as_raw_fd(),
+            libc::FALLOC_FL_KEEP_SIZE,
+            0,
+            len as libc::off_t,
+        )
+    };
+    if res == 0 {
+        return Ok(true);
+    }
+
+    let err = std::io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn preallocate(_fd: &std::fs::File, _len: u64) -> std::io::Result<bool> {
+    Ok(false)
+}
+
+/// Pass `pattern` to the kernel for the whole of `fd`.
+#[cfg(target_os = "linux")]
+pub fn fadvise(fd: &std::fs::File, pattern: AccessPattern) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let advice = match pattern {
+        AccessPattern::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+        AccessPattern::Random => libc::POSIX_FADV_RANDOM,
+    };
+    // returns the error number instead of setting errno
+    match unsafe { libc::posix_fadvise(fd.as_raw_fd(), 0, 0, advice) } {
+        0 => Ok(()),
+        errno => Err(std::io::Error::from_raw_os_error(errno)),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn fadvise(_fd: &std::fs::File, _pattern: AccessPattern) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::os::unix::fs::MetadataExt;
+
+    use crate::{io::AccessPattern, utils::testing::TempFile};
+
+    use super::{fadvise, preallocate};
+
+    #[test]
+    fn preallocate_keeps_size() {
+        let fd = TempFile::new("preallocate");
+
+        if !preallocate(&fd, 1 << 20).unwrap() {
+            return;
+        }
+        let meta = fd.metadata().unwrap();
+        assert_eq!(meta.len(), 0);
+        assert!(meta.blocks() * 512 >= 1 << 20, "blocks: {}", meta.blocks());
+    }
+
+    #[test]
+    fn fadvise_patterns() {
+        let fd = TempFile::new("fadvise");
+        fadvise(&fd, AccessPattern::Sequential).unwrap();
+        fadvise(&fd, AccessPattern::Random).unwrap();
+    }
+}
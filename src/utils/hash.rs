@@ -11,6 +11,41 @@ where
     }
 }
 
+/// A 32 bit hash over a byte key, the bloom filter derives its probes from it.
+/// Filters are only readable with the hasher that built them, so `name` must
+/// change whenever the hash of any key does.
+pub trait Hasher32: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn hash32(&self, data: &[u8]) -> u32;
+}
+
+/// A 64 bit hash over a byte key, the sharded cache picks a shard from it.
+pub trait Hasher64: Send + Sync {
+    fn hash64(&self, data: &[u8]) -> u64;
+}
+
+/// The default hasher, built on `basic_hash`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BasicHasher;
+
+impl Hasher32 for BasicHasher {
+    fn name(&self) -> &str {
+        "basic"
+    }
+
+    fn hash32(&self, data: &[u8]) -> u32 {
+        data.gen_basic_hash()
+    }
+}
+
+impl Hasher64 for BasicHasher {
+    fn hash64(&self, data: &[u8]) -> u64 {
+        let high = basic_hash(data, 0x9e3779b9) as u64;
+        (high << 32) | data.gen_basic_hash() as u64
+    }
+}
+
 pub(crate) fn basic_hash(data: &[u8], seed: u32) -> u32 {
     let m: u32 = 0xc6a4a793;
     let mut h = seed ^ (m.wrapping_mul(data.len() as u32));
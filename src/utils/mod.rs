@@ -1,5 +1,12 @@
 pub mod bloom;
+pub mod buffer_pool;
 pub mod caches;
+pub mod clock;
+pub mod encoding;
+pub mod fs;
 pub mod hash;
+pub mod scheduler;
+#[cfg(test)]
+pub(crate) mod testing;
 pub mod varint;
 pub mod comparators;
\ No newline at end of file
@@ -0,0 +1,236 @@
+use std::{
+    collections::VecDeque,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Condvar, Mutex},
+    thread::JoinHandle,
+};
+
+use crate::config::Config;
+
+/// Work run off the write path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Job {
+    Flush,
+    Compact { level: usize },
+}
+
+struct State {
+    queue: VecDeque<Job>,
+    running: usize,
+    shutdown: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    // signalled when a job is queued or on shutdown
+    work: Condvar,
+    // signalled when a job finishes
+    done: Condvar,
+    run: Box<dyn Fn(Job) + Send + Sync>,
+}
+
+/// Runs jobs on `max_background_jobs` threads, so at most that many run at once.
+/// A flush requested while another is still queued is dropped, the queued one
+/// flushes everything by the time it runs. Dropping the scheduler runs the jobs
+/// still queued and joins the threads. A job that panics is logged and counted
+/// as finished, its thread goes on with the next job.
+pub struct BackgroundScheduler {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl BackgroundScheduler {
+    pub fn new(config: &Config, run: impl Fn(Job) + Send + Sync + 'static) -> Self {
+        assert!(
+            config.max_background_jobs > 0,
+            "required max_background_jobs > 0"
+        );
+
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                running: 0,
+                shutdown: false,
+            }),
+            work: Condvar::new(),
+            done: Condvar::new(),
+            run: Box::new(run),
+        });
+        let workers = (0..config.max_background_jobs)
+            .map(|i| {
+                let shared = shared.clone();
+                std::thread::Builder::new()
+                    .name(format!("arisdb-bg-{}", i))
+                    .spawn(move || work(&shared))
+                    .expect("spawn background thread")
+            })
+            .collect();
+        Self { shared, workers }
+    }
+
+    /// Queue `job`, returns false if it was coalesced into a queued flush.
+    pub fn schedule(&self, job: Job) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+        if job == Job::Flush && state.queue.contains(&Job::Flush) {
+            return false;
+        }
+        state.queue.push_back(job);
+        self.shared.work.notify_one();
+        true
+    }
+
+    /// Block until no job is queued or running.
+    pub fn wait_idle(&self) {
+        let state = self.shared.state.lock().unwrap();
+        let _state = self
+            .shared
+            .done
+            .wait_while(state, |s| !s.queue.is_empty() || s.running > 0)
+            .unwrap();
+    }
+}
+
+fn work(shared: &Shared) {
+    let mut state = shared.state.lock().unwrap();
+    loop {
+        if let Some(job) = state.queue.pop_front() {
+            state.running += 1;
+            drop(state);
+            let res = panic::catch_unwind(AssertUnwindSafe(|| (shared.run)(job)));
+            if res.is_err() {
+                tracing::error!("background job panicked");
+            }
+            state = shared.state.lock().unwrap();
+            state.running -= 1;
+            shared.done.notify_all();
+        } else if state.shutdown {
+            return;
+        } else {
+            state = shared.work.wait(state).unwrap();
+        }
+    }
+}
+
+impl Drop for BackgroundScheduler {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().shutdown = true;
+        self.shared.work.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Barrier, Mutex,
+        },
+        time::Duration,
+    };
+
+    use crate::config::ConfigBuilder;
+
+    use super::{BackgroundScheduler, Job};
+
+    #[test]
+    fn concurrency_is_capped() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let finished = Arc::new(Mutex::new(vec![]));
+        // every job waits for two others, so all three threads run at once
+        let gate = Arc::new(Barrier::new(3));
+
+        let config = ConfigBuilder::default().max_background_jobs(3).build();
+        let (r, p, f, g) = (running.clone(), peak.clone(), finished.clone(), gate);
+        let scheduler = BackgroundScheduler::new(&config, move |job| {
+            let now = r.fetch_add(1, Ordering::SeqCst) + 1;
+            p.fetch_max(now, Ordering::SeqCst);
+            g.wait();
+            std::thread::sleep(Duration::from_millis(1));
+            r.fetch_sub(1, Ordering::SeqCst);
+            f.lock().unwrap().push(job);
+        });
+
+        for level in 0..21 {
+            assert!(scheduler.schedule(Job::Compact { level }));
+        }
+        scheduler.wait_idle();
+        assert_eq!(finished.lock().unwrap().len(), 21);
+        assert_eq!(peak.load(Ordering::SeqCst), 3);
+        assert_eq!(running.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn coalesce_queued_flushes() {
+        let flushes = Arc::new(AtomicUsize::new(0));
+        let gate = Arc::new(Barrier::new(2));
+
+        let config = ConfigBuilder::default().max_background_jobs(1).build();
+        let (f, g) = (flushes.clone(), gate.clone());
+        let scheduler = BackgroundScheduler::new(&config, move |job| {
+            if matches!(job, Job::Compact { level: 0 }) {
+                g.wait();
+            }
+            if job == Job::Flush {
+                f.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+
+        // the only thread is held by the compaction while the flushes queue up
+        assert!(scheduler.schedule(Job::Compact { level: 0 }));
+        assert!(scheduler.schedule(Job::Flush));
+        assert!(!scheduler.schedule(Job::Flush));
+        assert!(scheduler.schedule(Job::Compact { level: 1 }));
+        assert!(!scheduler.schedule(Job::Flush));
+        gate.wait();
+        scheduler.wait_idle();
+        assert_eq!(flushes.load(Ordering::SeqCst), 1);
+
+        // a flush is requested again once the queued one has run
+        assert!(scheduler.schedule(Job::Flush));
+        scheduler.wait_idle();
+        assert_eq!(flushes.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn drop_runs_queued_jobs() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let config = ConfigBuilder::default().build();
+        let c = count.clone();
+        let scheduler = BackgroundScheduler::new(&config, move |_| {
+            c.fetch_add(1, Ordering::SeqCst);
+        });
+        for level in 0..10 {
+            scheduler.schedule(Job::Compact { level });
+        }
+        drop(scheduler);
+        assert_eq!(count.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn panicking_job() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let config = ConfigBuilder::default().max_background_jobs(1).build();
+        let c = count.clone();
+        let scheduler = BackgroundScheduler::new(&config, move |job| {
+            if job == Job::Flush {
+                panic!("flush failed");
+            }
+            c.fetch_add(1, Ordering::SeqCst);
+        });
+
+        scheduler.schedule(Job::Flush);
+        scheduler.wait_idle();
+
+        // the only thread survives the panic and runs the next jobs
+        scheduler.schedule(Job::Compact { level: 0 });
+        scheduler.schedule(Job::Flush);
+        scheduler.schedule(Job::Compact { level: 1 });
+        scheduler.wait_idle();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        drop(scheduler);
+    }
+}
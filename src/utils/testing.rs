@@ -0,0 +1,70 @@
+use std::{
+    fs::File,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// A file in the temp dir named after a test, opened for reading and writing and
+/// removed when dropped.
+pub(crate) struct TempFile {
+    path: PathBuf,
+    fd: Arc<File>,
+}
+
+impl TempFile {
+    pub(crate) fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("arisdb-{}-{}", name, std::process::id()));
+        let fd = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(true)
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        Self {
+            path,
+            fd: Arc::new(fd),
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The file for readers and builders that share it.
+    pub(crate) fn shared(&self) -> Arc<File> {
+        self.fd.clone()
+    }
+}
+
+impl Deref for TempFile {
+    type Target = File;
+
+    fn deref(&self) -> &File {
+        &self.fd
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::FileExt;
+
+    use super::TempFile;
+
+    #[test]
+    fn removed_on_drop() {
+        let file = TempFile::new("temp_file_removed_on_drop");
+        let path = file.path().to_path_buf();
+        file.write_all_at(b"hello", 0).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 5);
+        drop(file);
+        assert!(!path.exists());
+    }
+}